@@ -0,0 +1,119 @@
+use clap::{App, Arg, ArgMatches};
+
+/// Options passed through to every `cargo test` a subcommand runs, along with arguments for the
+/// test executables after `--`.
+#[derive(Debug, Default, PartialEq)]
+pub struct CargoArgs {
+    pub args: Vec<String>,
+    pub test_args: Vec<String>,
+}
+
+/// Flags without a value, passed through as they are.
+static FLAGS: &[(&str, &str)] = &[
+    ("all-features", "Activate all available features."),
+    ("no-default-features", "Don't activate the `default` feature."),
+    ("release", "Build and test in release mode."),
+    ("lib", "Only test the library."),
+    ("bins", "Only test binaries."),
+    ("tests", "Only test integration tests."),
+    ("frozen", "Require Cargo.lock and the cache to be up to date."),
+    ("locked", "Require Cargo.lock to be up to date."),
+    ("offline", "Run without accessing the network."),
+];
+
+/// Options which take a value, passed through once for each time they're given.
+static OPTIONS: &[(&str, &str)] = &[
+    ("features", "Space or comma separated list of features to activate."),
+    ("profile", "Build with the given profile."),
+    ("target", "Build for the target triple."),
+    ("target-dir", "Directory for all generated artifacts."),
+    ("test", "Only test the given integration test target, may be repeated."),
+    ("bin", "Only test the given binary, may be repeated."),
+    ("jobs", "Number of parallel build jobs."),
+];
+
+impl CargoArgs {
+    /// Adds the passed through `cargo test` options to a subcommand.
+    pub fn args<'a, 'b>(mut app: App<'a, 'b>) -> App<'a, 'b> {
+        for &(name, help) in FLAGS {
+            app = app.arg(Arg::with_name(name).long(name).help(help));
+        }
+
+        for &(name, help) in OPTIONS {
+            app = app.arg(Arg::with_name(name)
+                              .long(name)
+                              .takes_value(true)
+                              .multiple(true)
+                              .number_of_values(1)
+                              .help(help));
+        }
+
+        app.arg(Arg::with_name("test_args")
+                    .multiple(true)
+                    .last(true)
+                    .help("Arguments passed to the test executables."))
+    }
+
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        let mut args = Vec::new();
+
+        for &(name, _) in FLAGS {
+            if matches.is_present(name) {
+                args.push(format!("--{}", name));
+            }
+        }
+
+        for &(name, _) in OPTIONS {
+            for value in matches.values_of(name).into_iter().flatten() {
+                args.push(format!("--{}", name));
+                args.push(value.to_owned());
+            }
+        }
+
+        CargoArgs {
+            args,
+            test_args: matches
+                .values_of("test_args")
+                .map(|values| values.map(str::to_owned).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Arguments for `run_cargo_test`, with extra cargo and test executable arguments added.
+    pub fn with(&self, cargo_args: &[String], test_args: &[String]) -> Vec<String> {
+        let mut all_args = self.args.clone();
+        all_args.extend(cargo_args.iter().cloned());
+
+        if !self.test_args.is_empty() || !test_args.is_empty() {
+            all_args.push("--".to_owned());
+            all_args.extend(self.test_args.iter().cloned());
+            all_args.extend(test_args.iter().cloned());
+        }
+
+        all_args
+    }
+}
+
+#[cfg(test)]
+mod cargo_args_tests {
+    use super::CargoArgs;
+    use clap::{App, SubCommand};
+
+    #[test]
+    fn it_should_pass_through_cargo_options() {
+        let app = App::new("cargo-snapshot").subcommand(CargoArgs::args(SubCommand::with_name("update")));
+        let matches = app.get_matches_from(vec![
+            "cargo-snapshot", "update", "--features", "json", "--release", "--test", "simple",
+            "--features", "yaml", "--", "--test-threads", "1",
+        ]);
+        let cargo_args = CargoArgs::from_matches(matches.subcommand_matches("update").unwrap());
+
+        assert_eq!(
+            cargo_args.with(&["-p".to_owned(), "app".to_owned()], &["--exact".to_owned()]),
+            vec![
+                "--release", "--features", "json", "--features", "yaml", "--test", "simple", "-p",
+                "app", "--", "--test-threads", "1", "--exact",
+            ]
+        );
+    }
+}
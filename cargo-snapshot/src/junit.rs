@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use snapshot::events::{self, Event};
+
+use crate::parse_tests::Suite;
+use crate::test_run::{failed_snapshots, suite_crate_name};
+use crate::{ResultExt, SnapResult};
+
+/// Writes a run's results as JUnit XML, with the snapshot diff as the message of every test
+/// whose snapshot mismatched or was missing.
+pub fn write_junit(path: &Path, suites: &[Suite], events: &[Event]) -> SnapResult<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).chain_err(|| format!("unable to create {:?}", dir))?;
+    }
+    fs::write(path, render_junit(suites, events))
+        .chain_err(|| format!("unable to write JUnit report {:?}", path))?;
+    Ok(())
+}
+
+fn render_junit(suites: &[Suite], events: &[Event]) -> String {
+    let snapshot_failures: BTreeMap<String, Event> = failed_snapshots(events.to_vec())
+        .into_iter()
+        .map(|event| (event.key.clone(), event))
+        .collect();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+
+    for suite in suites {
+        let crate_name = suite_crate_name(suite.name).unwrap_or(suite.name);
+        let failures = suite.tests.iter().filter(|test| test.status == "fail").count();
+        let skipped = suite.tests.iter().filter(|test| test.status == "ignored").count();
+
+        xml.push_str(&format!("  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">\n",
+                              escape(crate_name),
+                              suite.tests.len(),
+                              failures,
+                              skipped));
+
+        for test in &suite.tests {
+            let key = format!("{}::{}", crate_name, test.name);
+            let (classname, name) = match key.rfind("::") {
+                Some(idx) => (&key[..idx], &key[idx + 2..]),
+                None => (crate_name, key.as_str()),
+            };
+            xml.push_str(&format!("    <testcase classname=\"{}\" name=\"{}\"",
+                                  escape(classname),
+                                  escape(name)));
+
+            match test.status {
+                "fail" => {
+                    let (message, body) = match snapshot_failures.get(&key) {
+                        Some(event) => {
+                            let outcome = match event.outcome {
+                                events::Outcome::Missing => "missing",
+                                _ => "mismatched",
+                            };
+                            (format!("snapshot {} in {}", outcome, event.file.display()),
+                             event.message.clone().unwrap_or_default())
+                        }
+                        None => ("test failed".to_owned(), test.error.unwrap_or_default().to_owned()),
+                    };
+                    xml.push_str(&format!(">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                                          escape(&message),
+                                          escape(&body)));
+                }
+                "ignored" => xml.push_str(">\n      <skipped/>\n    </testcase>\n"),
+                _ => xml.push_str("/>\n"),
+            }
+        }
+
+        xml.push_str("  </testsuite>\n");
+    }
+
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+/// Escapes text for XML, dropping terminal color codes and other control characters XML can't
+/// hold.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\u{1b}' => {
+                // skip an escape sequence like `ESC[31m`
+                if chars.peek() == Some(&'[') {
+                    for c in chars.by_ref() {
+                        if c.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                }
+            }
+            '\n' | '\t' | '\r' => escaped.push(c),
+            c if c.is_control() => (),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod junit_tests {
+    use super::{escape, render_junit};
+    use crate::parse_tests::{Suite, Test};
+    use crate::test_support::event;
+    use snapshot::events::{Event, Outcome};
+
+    #[test]
+    fn it_should_strip_colors_and_escape() {
+        assert_eq!(escape("\u{1b}[31m<left>\u{1b}[0m & \"x\""), "&lt;left&gt; &amp; &quot;x&quot;");
+    }
+
+    #[test]
+    fn it_should_report_snapshot_diffs() {
+        let suites = vec![Suite {
+            name: "tests/simple.rs (target/debug/deps/simple-f90ad51e6d4ff9c3)",
+            state: "fail",
+            passed: 1,
+            failed: 2,
+            ignored: 1,
+            measured: 0,
+            total: 4,
+            tests: vec![
+                Test { name: "test::matched", status: "pass", error: None },
+                Test { name: "test::mismatched", status: "fail", error: Some("panicked") },
+                Test { name: "test::panicked", status: "fail", error: Some("oops") },
+                Test { name: "test::ignored", status: "ignored", error: None },
+            ],
+        }];
+        let events = vec![Event {
+            message: Some("doesn't match\n  ~ .x: 1 -> 2\n".to_owned()),
+            ..event("simple::test::mismatched", Outcome::Mismatched)
+        }];
+
+        let xml = render_junit(&suites, &events);
+        assert!(xml.contains(
+            "<testsuite name=\"simple\" tests=\"4\" failures=\"2\" skipped=\"1\">"));
+        assert!(xml.contains("<testcase classname=\"simple::test\" name=\"matched\"/>"));
+        assert!(xml.contains(
+            "<failure message=\"snapshot mismatched in tests/__snapshots__/simple.rs.snap\">\
+             doesn&apos;t match\n  ~ .x: 1 -&gt; 2\n</failure>"));
+        assert!(xml.contains("<failure message=\"test failed\">oops</failure>"));
+        assert!(xml.contains("<skipped/>"));
+    }
+}
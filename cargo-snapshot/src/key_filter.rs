@@ -0,0 +1,150 @@
+use std::path::{Component, Path, PathBuf};
+
+use clap::ArgMatches;
+use regex::Regex;
+use snapshot::events::Event;
+
+use crate::{ResultExt, SnapResult};
+
+/// Chooses snapshots by key pattern and by file, for updating without prompts.
+#[derive(Debug)]
+pub struct KeyFilter {
+    /// A key matches if it matches any of these.
+    patterns: Vec<Regex>,
+    /// Snapshot or source files, absolute or relative to the current directory. A snapshot
+    /// matches if it's in any of them.
+    files: Vec<PathBuf>,
+}
+
+impl KeyFilter {
+    /// Reads `--filter`, `--regex` and `--file`, returning `None` if neither filter was given.
+    pub fn from_matches(matches: &ArgMatches) -> SnapResult<Option<Self>> {
+        let patterns: Vec<&str> = matches.values_of("filter").into_iter().flatten().collect();
+        let files: Vec<PathBuf> = matches
+            .values_of("file")
+            .into_iter()
+            .flatten()
+            .map(PathBuf::from)
+            .collect();
+
+        if patterns.is_empty() && files.is_empty() {
+            return Ok(None);
+        }
+
+        let is_regex = matches.is_present("regex");
+        let patterns = patterns
+            .into_iter()
+            .map(|pattern| {
+                let source = if is_regex {
+                    pattern.to_owned()
+                } else {
+                    glob_to_regex(pattern)
+                };
+                Regex::new(&source).chain_err(|| format!("invalid filter {:?}", pattern))
+            })
+            .collect::<SnapResult<_>>()?;
+
+        Ok(Some(KeyFilter { patterns, files }))
+    }
+
+    /// Whether the snapshot behind `event` is chosen, resolving files relative to `cwd`.
+    pub fn matches(&self, event: &Event, cwd: &Path) -> bool {
+        let key_matches =
+            self.patterns.is_empty() || self.patterns.iter().any(|p| p.is_match(&event.key));
+
+        let file_matches = self.files.is_empty()
+            || self.files.iter().any(|file| {
+                let file = normalize(&cwd.join(file));
+                file == normalize(&event.manifest_dir.join(&event.file))
+                    || file == normalize(&event.manifest_dir.join(&event.source_file))
+            });
+
+        key_matches && file_matches
+    }
+}
+
+/// `path` with its `.` and `..` components resolved, without looking at the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// An anchored regex for a glob, where `*` matches any run of characters and `?` matches one.
+fn glob_to_regex(glob: &str) -> String {
+    let mut source = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => source.push_str(".*"),
+            '?' => source.push('.'),
+            c => source.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    source.push('$');
+    source
+}
+
+#[cfg(test)]
+mod key_filter_tests {
+    use super::{glob_to_regex, KeyFilter};
+    use crate::test_support;
+    use regex::Regex;
+    use snapshot::events::{Event, Outcome};
+    use std::path::{Path, PathBuf};
+
+    fn event(key: &str) -> Event {
+        test_support::event(key, Outcome::Mismatched)
+    }
+
+    #[test]
+    fn it_should_match_globs() {
+        let glob = Regex::new(&glob_to_regex("simple::test::*_snapshot")).unwrap();
+
+        assert!(glob.is_match("simple::test::compound_snapshot"));
+        assert!(glob.is_match("simple::test::sub_test::simple_snapshot"));
+        assert!(!glob.is_match("second::test::another_simple_snapshot"));
+        assert!(!glob.is_match("simple::test::compound_snapshots"));
+    }
+
+    #[test]
+    fn it_should_match_files_relative_to_cwd() {
+        let filter = KeyFilter {
+            patterns: vec![Regex::new(&glob_to_regex("*compound*")).unwrap()],
+            files: vec![PathBuf::from("tests/simple.rs")],
+        };
+
+        assert!(filter.matches(&event("simple::test::compound_snapshot"), Path::new("/ws/app")));
+        assert!(!filter.matches(&event("simple::test::simple_snapshot"), Path::new("/ws/app")));
+        assert!(!filter.matches(&event("simple::test::compound_snapshot"), Path::new("/ws")));
+
+        let by_snap_file = KeyFilter {
+            patterns: Vec::new(),
+            files: vec![PathBuf::from("app/tests/__snapshots__/simple.rs.snap")],
+        };
+        assert!(by_snap_file.matches(&event("simple::test::simple_snapshot"), Path::new("/ws")));
+    }
+
+    #[test]
+    fn it_should_normalize_file_paths() {
+        let by_file = |file: &str| KeyFilter {
+            patterns: Vec::new(),
+            files: vec![PathBuf::from(file)],
+        };
+        let event = event("simple::test::simple_snapshot");
+
+        assert!(by_file("./tests/simple.rs").matches(&event, Path::new("/ws/app")));
+        assert!(by_file("tests/../tests/simple.rs").matches(&event, Path::new("/ws/app")));
+        assert!(by_file("/ws/app/tests/simple.rs").matches(&event, Path::new("/ws/other")));
+        assert!(by_file("../app/tests/__snapshots__/simple.rs.snap")
+                    .matches(&event, Path::new("/ws/other")));
+        assert!(!by_file("../tests/simple.rs").matches(&event, Path::new("/ws/app")));
+    }
+}
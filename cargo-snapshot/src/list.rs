@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde_derive::Serialize;
+use serde_json::Value;
+use snapshot::events::{self, Event};
+
+use crate::snap_files::SnapFiles;
+use crate::workspace::{Package, Workspace};
+use crate::{relative_to, SnapResult};
+
+/// A recorded snapshot, as listed by `cargo snapshot list`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Entry {
+    pub key: String,
+    pub package: String,
+    /// The test's source file, relative to the current directory.
+    pub source_file: String,
+    /// The `.snap` file holding the snapshot, relative to the current directory.
+    pub snap_file: String,
+    /// The JSON type of the recorded value.
+    pub value_type: &'static str,
+    /// Length of the recorded value as compact JSON.
+    pub size: usize,
+    /// What happened to the snapshot in the last test run `cargo snapshot` did, if it ran.
+    pub status: Option<events::Outcome>,
+    /// Whether a new value is waiting for review in a pending file.
+    pub pending: bool,
+}
+
+/// Every snapshot recorded in `packages`, with their status from the last run's event log.
+pub fn list_entries(workspace: &Workspace,
+                    packages: &[&Package],
+                    cwd: &Path)
+                    -> SnapResult<Vec<Entry>> {
+    let logged = events::read_log(&workspace.event_log())?;
+
+    let mut last_events = BTreeMap::new();
+    for event in &logged {
+        last_events.insert((event.key.as_str(), event.manifest_dir.as_path()), event);
+    }
+
+    let mut entries = Vec::new();
+    for package in packages {
+        let found = SnapFiles::find(package.manifest_dir(), &package.config()?.layout())?;
+        entries.extend(package_entries(package, &found, &last_events, cwd));
+    }
+
+    Ok(entries)
+}
+
+fn package_entries(package: &Package,
+                   found: &SnapFiles,
+                   last_events: &BTreeMap<(&str, &Path), &Event>,
+                   cwd: &Path)
+                   -> Vec<Entry> {
+    let manifest_dir = package.manifest_dir();
+    let mut entries = Vec::new();
+
+    for snap_file in &found.snapshots {
+        for (key, snapshot) in &snap_file.contents {
+            let source_file: PathBuf = snapshot.file.iter().collect();
+            let pending = found
+                .pending
+                .iter()
+                .any(|pending| pending.contents.contains_key(key));
+
+            entries.push(Entry {
+                key: key.clone(),
+                package: package.name.clone(),
+                source_file: relative_to(cwd, &manifest_dir.join(source_file)),
+                snap_file: relative_to(cwd, &snap_file.path),
+                value_type: value_type(&snapshot.recorded_value),
+                size: snapshot.recorded_value.to_string().len(),
+                status: last_events
+                    .get(&(key.as_str(), manifest_dir))
+                    .map(|event| event.outcome),
+                pending,
+            });
+        }
+    }
+
+    entries
+}
+
+fn value_type(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn status_label(status: Option<events::Outcome>) -> &'static str {
+    match status {
+        Some(events::Outcome::Matched) => "matched",
+        Some(events::Outcome::Mismatched) => "mismatched",
+        Some(events::Outcome::Missing) => "missing",
+        Some(events::Outcome::Written) => "written",
+        None => "not run",
+    }
+}
+
+pub fn print_entries(entries: &[Entry]) {
+    for entry in entries {
+        let pending = if entry.pending { ", pending review" } else { "" };
+        println!("{} ({}, {} bytes, {}{})",
+                 entry.key,
+                 entry.value_type,
+                 entry.size,
+                 status_label(entry.status),
+                 pending);
+        println!("    {} from {}", entry.snap_file, entry.source_file);
+    }
+
+    println!("\n{} snapshot(s)", entries.len());
+}
+
+#[cfg(test)]
+mod list_tests {
+    use super::package_entries;
+    use crate::snap_files::{SnapFile, SnapFiles};
+    use crate::test_support::{contents, event};
+    use crate::workspace::Package;
+    use serde_json::Value;
+    use snapshot::events::Outcome;
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+
+    fn snap_file(path: &str, entries: &[(&str, Value)]) -> SnapFile {
+        SnapFile {
+            path: PathBuf::from(path),
+            contents: contents(entries),
+        }
+    }
+
+    #[test]
+    fn it_should_list_recorded_snapshots() {
+        let package = Package {
+            id: "app 0.1.0".to_owned(),
+            name: "app".to_owned(),
+            manifest_path: PathBuf::from("/ws/app/Cargo.toml"),
+            targets: Vec::new(),
+        };
+        let found = SnapFiles {
+            snapshots: vec![snap_file("/ws/app/tests/__snapshots__/simple.rs.snap",
+                                      &[("compound", Value::from(vec![1, 2])),
+                                        ("simple", Value::from("hi"))])],
+            pending: vec![snap_file("/ws/app/tests/__snapshots__/simple.rs.snap.pending",
+                                    &[("simple", Value::from("hello"))])],
+        };
+
+        let event = event("simple::test::compound", Outcome::Matched);
+        let mut last_events = BTreeMap::new();
+        last_events.insert((event.key.as_str(), event.manifest_dir.as_path()), &event);
+
+        let entries = package_entries(&package, &found, &last_events, Path::new("/ws"));
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| (e.key.as_str(), e.value_type, e.size, e.status, e.pending))
+            .collect();
+
+        assert_eq!(summary,
+                   vec![("simple::test::compound", "array", 5, Some(Outcome::Matched), false),
+                        ("simple::test::simple", "string", 4, None, true)]);
+        assert_eq!(entries[0].snap_file, "app/tests/__snapshots__/simple.rs.snap");
+        assert_eq!(entries[0].source_file, "app/tests/simple.rs");
+    }
+}
@@ -1,4 +1,21 @@
+mod cargo_args;
+mod junit;
+mod key_filter;
+mod list;
+mod merge;
+mod parse_json;
 mod parse_tests;
+mod report;
+mod rev_diff;
+mod review;
+mod show;
+mod snap_files;
+mod stats;
+mod test_run;
+#[cfg(test)]
+mod test_support;
+mod watch;
+mod workspace;
 
 // Even though we can `use` the macros directly in 2018, these macros have other
 //  macro dependencies which are annoying to `use` and make the code a little less clear.
@@ -6,15 +23,29 @@ mod parse_tests;
 #[macro_use] extern crate clap;
 #[macro_use] extern crate error_chain;
 
-use std::ffi::OsStr;
-use std::fs::File;
-use std::io::BufReader;
-
-use clap::{Arg, SubCommand};
-use dialoguer::{Checkboxes, Select};
-use duct::cmd;
-use snapshot::SnapFileContents;
-use walkdir::WalkDir;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use clap::{AppSettings, Arg, SubCommand};
+use console::Term;
+use snapshot::config::{UPDATE_SNAPSHOTS_FILTER_VAR, UPDATE_SNAPSHOTS_VAR};
+use snapshot::events::{self, Event};
+
+use crate::cargo_args::CargoArgs;
+use crate::junit::write_junit;
+use crate::key_filter::KeyFilter;
+use crate::list::{list_entries, print_entries};
+use crate::merge::merge_driver;
+use crate::report::report;
+use crate::rev_diff::diff_against;
+use crate::review::{review_and_apply, review_items};
+use crate::show::show;
+use crate::snap_files::{find_obsolete, SnapFiles};
+use crate::stats::Stats;
+use crate::test_run::{classify, failed_snapshots, print_summary, print_unrelated_failures, recorded_keys,
+                      run_cargo_test, select_tests, unrelated_failures};
+use crate::watch::watch;
+use crate::workspace::{Package, PackageSelection, Workspace};
 
 error_chain!{
     types {
@@ -29,33 +60,209 @@ error_chain!{
 quick_main!(run);
 
 fn run() -> SnapResult<()> {
+    // cargo passes the subcommand name along when invoked as `cargo snapshot`
+    let mut args: Vec<_> = ::std::env::args_os().collect();
+    if args.get(1).is_some_and(|arg| arg == "snapshot") {
+        args.remove(1);
+    }
+
     let input = app_from_crate!()
-        .subcommand(
+        .subcommand(CargoArgs::args(PackageSelection::args(
             SubCommand::with_name("update")
                 .about("Update snapshots.")
                 .arg(Arg::with_name("all").short("a").long("all").help(
                     "Unconditionally update all snapshots, including unrecorded ones.",
-                )),
+                ))
+                .arg(Arg::with_name("filter")
+                         .long("filter")
+                         .takes_value(true)
+                         .multiple(true)
+                         .number_of_values(1)
+                         .conflicts_with("all")
+                         .help("Update failed snapshots whose key matches this glob without \
+                                prompting, may be repeated."))
+                .arg(Arg::with_name("regex")
+                         .long("regex")
+                         .requires("filter")
+                         .help("Treat --filter patterns as regular expressions."))
+                .arg(Arg::with_name("file")
+                         .long("file")
+                         .takes_value(true)
+                         .multiple(true)
+                         .number_of_values(1)
+                         .conflicts_with("all")
+                         .help("Update failed snapshots recorded in this .snap file or tested by \
+                                this source file without prompting, may be repeated.")),
+        )))
+        .subcommand(PackageSelection::args(
+            SubCommand::with_name("check")
+                .about("Fail if any snapshots are pending review or obsolete, for use in CI.")
+                .after_help("A snapshot is obsolete when its source file is gone, or when the file \
+                             no longer contains `fn <test function>`. That's a text search, so a \
+                             match in a comment, a string or another module still counts and \
+                             keeps an obsolete snapshot around."),
+        ))
+        .subcommand(PackageSelection::args(
+            SubCommand::with_name("list")
+                .about("List every recorded snapshot, with the status of its last test run.")
+                .arg(Arg::with_name("json").long("json").help("Print the list as JSON.")),
+        ))
+        .subcommand(PackageSelection::args(
+            SubCommand::with_name("diff")
+                .about("Compare snapshots in the working tree with those at a git revision, key by \
+                        key.")
+                .arg(Arg::with_name("rev")
+                         .default_value("HEAD")
+                         .help("The git revision to compare against.")),
+        ))
+        .subcommand(
+            SubCommand::with_name("merge-driver")
+                .about("Merge .snap files key by key, for use as a git merge driver.")
+                .arg(Arg::with_name("base").required(true).help("The common ancestor, %O."))
+                .arg(Arg::with_name("ours")
+                         .required(true)
+                         .help("Our version, %A, which is replaced by the merge."))
+                .arg(Arg::with_name("theirs").required(true).help("Their version, %B.")),
+        )
+        .subcommand(CargoArgs::args(PackageSelection::args(
+            SubCommand::with_name("report")
+                .about("Run the tests and write an HTML page showing every snapshot that needs \
+                        review, with side-by-side diffs.")
+                .arg(Arg::with_name("html")
+                         .long("html")
+                         .takes_value(true)
+                         .value_name("DIR")
+                         .required(true)
+                         .help("Directory to write the report's index.html to.")),
+        )))
+        .subcommand(PackageSelection::args(
+            SubCommand::with_name("show")
+                .about("Print a recorded snapshot, found by its key or part of it.")
+                .arg(Arg::with_name("key")
+                         .required(true)
+                         .help("The snapshot's key, or enough of it to pick one snapshot.")),
+        ))
+        .subcommand(PackageSelection::args(
+            SubCommand::with_name("stats")
+                .about("Report snapshot counts and sizes, and find large, duplicated or unused \
+                        snapshots.")
+                .arg(Arg::with_name("top")
+                         .long("top")
+                         .takes_value(true)
+                         .default_value("10")
+                         .help("How many of the largest snapshots to list.")),
+        ))
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Run `cargo test` and summarize which snapshots matched, mismatched, were \
+                        missing or were written.")
+                .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(Arg::with_name("junit")
+                         .long("junit")
+                         .takes_value(true)
+                         .value_name("FILE")
+                         .help("Also write the results as JUnit XML, with snapshot diffs in the \
+                                failure messages."))
+                .arg(Arg::with_name("cargo_args")
+                         .multiple(true)
+                         .help("Arguments passed through to `cargo test`, including -p, \
+                                --workspace and --exclude."))
+                .arg(Arg::with_name("test_args")
+                         .multiple(true)
+                         .last(true)
+                         .help("Arguments after `--`, passed through to the test executables.")),
         )
-        .get_matches();
+        .subcommand(CargoArgs::args(PackageSelection::args(
+            SubCommand::with_name("watch")
+                .about("Rerun affected tests whenever sources change, and review snapshots as \
+                        they fail."),
+        )))
+        .get_matches_from(&args);
+
+    // git runs merge drivers mid-merge, when the manifest may not even parse
+    if let Some(merge_matches) = input.subcommand_matches("merge-driver") {
+        let path = |name| Path::new(merge_matches.value_of_os(name).unwrap_or_default());
+        return merge_driver(path("base"), path("ours"), path("theirs"));
+    }
+
+    let cwd = ::std::env::current_dir()
+        .chain_err(|| "unable to read cwd")?;
+    let workspace = Workspace::load()?;
 
     if let Some(update_matches) = input.subcommand_matches("update") {
+        let selection = PackageSelection::from_matches(update_matches);
+        let packages = workspace.select(&selection, &cwd)?;
+        let cargo_args = CargoArgs::from_matches(update_matches);
+        let key_filter = KeyFilter::from_matches(update_matches)?;
+
+        for package in &packages {
+            if package.config()?.is_strict() {
+                bail!("refusing to update snapshots, writes are disabled in CI or by `strict`");
+            }
+        }
+
         if update_matches.is_present("all") {
-            let output = cmd("cargo", &["test"])
-                .env("UPDATE_SNAPSHOTS", "1")
-                .stdout_capture()
-                .stderr_capture()
-                .run()
-                .chain_err(|| "unable to execute cargo")?;
-
-            if !output.status.success() {
-                // TODO(dikaiosune) print what failed
+            let all_args = cargo_args.with(&PackageSelection::cargo_args(&packages), &[]);
+            let run = run_cargo_test(&workspace, &all_args, &[(UPDATE_SNAPSHOTS_VAR, "always")], false)?;
+
+            if !run.success {
+                print_unrelated_failures(&unrelated_failures(&run.suites(), &run.events));
                 bail!("unable to update all snapshots!");
             }
+        } else if let Some(key_filter) = key_filter {
+            filtered_process(&workspace, &packages, &cargo_args, &key_filter, &cwd)?;
         } else {
-            interactive_process()?;
+            interactive_process(&workspace, &packages, &cargo_args, &cwd)?;
         }
         println!("\nAll updates processed!");
+    } else if let Some(check_matches) = input.subcommand_matches("check") {
+        let selection = PackageSelection::from_matches(check_matches);
+        check(&cwd, &workspace.select(&selection, &cwd)?)?;
+    } else if let Some(list_matches) = input.subcommand_matches("list") {
+        let selection = PackageSelection::from_matches(list_matches);
+        let entries = list_entries(&workspace, &workspace.select(&selection, &cwd)?, &cwd)?;
+
+        if list_matches.is_present("json") {
+            let json = serde_json::to_string_pretty(&entries)
+                .chain_err(|| "unable to serialize snapshot list")?;
+            println!("{}", json);
+        } else {
+            print_entries(&entries);
+        }
+    } else if let Some(diff_matches) = input.subcommand_matches("diff") {
+        let selection = PackageSelection::from_matches(diff_matches);
+        let rev = diff_matches.value_of("rev").unwrap_or("HEAD");
+        diff_against(&workspace, &workspace.select(&selection, &cwd)?, rev)?;
+    } else if let Some(report_matches) = input.subcommand_matches("report") {
+        let selection = PackageSelection::from_matches(report_matches);
+        let out_dir = Path::new(report_matches.value_of_os("html").unwrap_or_default());
+        report(&workspace,
+               &workspace.select(&selection, &cwd)?,
+               &CargoArgs::from_matches(report_matches),
+               out_dir,
+               &cwd)?;
+    } else if let Some(show_matches) = input.subcommand_matches("show") {
+        let selection = PackageSelection::from_matches(show_matches);
+        let key = show_matches.value_of("key").unwrap_or_default();
+        show(&workspace.select(&selection, &cwd)?, key, &cwd)?;
+    } else if let Some(stats_matches) = input.subcommand_matches("stats") {
+        let selection = PackageSelection::from_matches(stats_matches);
+        let top = value_t!(stats_matches, "top", usize).unwrap_or_else(|e| e.exit());
+        Stats::collect(&workspace, &workspace.select(&selection, &cwd)?, top)?.print(&cwd);
+    } else if let Some(watch_matches) = input.subcommand_matches("watch") {
+        let selection = PackageSelection::from_matches(watch_matches);
+        watch(&workspace,
+              &workspace.select(&selection, &cwd)?,
+              &CargoArgs::from_matches(watch_matches),
+              &cwd)?;
+    } else if let Some(test_matches) = input.subcommand_matches("test") {
+        let mut cargo_args = test_matches.values_of_lossy("cargo_args").unwrap_or_default();
+        if let Some(test_args) = test_matches.values_of_lossy("test_args") {
+            cargo_args.push("--".to_owned());
+            cargo_args.extend(test_args);
+        }
+        test(&workspace, &cargo_args, test_matches.value_of_os("junit").map(Path::new))?;
     } else {
         panic!("unsupported command");
     }
@@ -63,145 +270,158 @@ fn run() -> SnapResult<()> {
     Ok(())
 }
 
-fn interactive_process() -> SnapResult<()> {
-    let test_function_names = find_existing_snapshot_test_names()?;
-    let mut failed_tests = Vec::new();
+fn interactive_process(workspace: &Workspace,
+                       packages: &[&Package],
+                       cargo_args: &CargoArgs,
+                       cwd: &Path)
+                       -> SnapResult<()> {
+    if !Term::stdout().is_term() {
+        bail!("reviewing snapshots needs a terminal, use `--all`, `--filter` or `--file` instead");
+    }
 
     println!("Checking for out of date snapshot tests...");
-    for test_fn in &test_function_names {
-        if !test_fn.success()? {
-            failed_tests.push(test_fn);
-        }
-    }
+    let failed_tests = find_failed_snapshots(workspace, packages, cargo_args)?;
+    let items = review_items(packages, &failed_tests, cwd)?;
 
-    if failed_tests.is_empty() {
+    if items.is_empty() {
         println!("\nNo snapshot tests require an update!");
         ::std::process::exit(0);
-    } else {
-        println!("\nPlease select which snapshot tests should be updated:");
-        println!("  <Space> to select, <Enter> to submit\n");
+    }
 
-        let mut menu = Checkboxes::new();
-        for failed in &failed_tests {
-            menu.item(&failed.0);
-        }
+    if !review_and_apply(&items)? {
+        println!("\nExiting without changes...");
+        ::std::process::exit(1);
+    }
+    Ok(())
+}
 
-        let all_to_update = menu.interact()
-            .chain_err(|| "error accepting user selections")?;
-
-        for fn_idx in all_to_update {
-            let fn_to_update = &failed_tests[fn_idx];
-            println!("Updating {}...", &fn_to_update.0);
-
-            // attempt to update the snapshot in a loop, allowing user to change code in bt runs
-            let mut run_test = true;
-            while run_test {
-                let run_output = cmd("cargo", &["test", &fn_to_update.0])
-                    .env("UPDATE_SNAPSHOTS", "1")
-                    .stdout_capture()
-                    .stderr_capture()
-                    .run()
-                    .chain_err(|| "unable to execute cargo")?;
-
-                if run_output.status.success() {
-                    run_test = false;
-                } else {
-                    println!("\nUpdating {} failed! What would you like to do?",
-                             &fn_to_update.0);
-
-                    match capture_failure_selection()? {
-                        TestFailureSelection::Retry => continue,
-                        TestFailureSelection::Skip => break,
-                        TestFailureSelection::Abort => {
-                            println!("\nExiting...");
-                            ::std::process::exit(1);
-                        }
-                    }
-                }
-            }
-        }
+/// Updates the failed snapshots chosen by `key_filter` without prompting.
+fn filtered_process(workspace: &Workspace,
+                    packages: &[&Package],
+                    cargo_args: &CargoArgs,
+                    key_filter: &KeyFilter,
+                    cwd: &Path)
+                    -> SnapResult<()> {
+    println!("Checking for out of date snapshot tests...");
+    let failed_tests = find_failed_snapshots(workspace, packages, cargo_args)?;
+
+    let to_update: Vec<&Event> = failed_tests
+        .iter()
+        .filter(|event| key_filter.matches(event, cwd))
+        .collect();
 
+    if to_update.is_empty() {
+        println!("\nNo matching snapshot tests require an update!");
         Ok(())
+    } else {
+        update_events(workspace, cargo_args, to_update)
     }
 }
 
-struct FnName(String);
+/// Updates each snapshot with one run per test target, failing if any aren't written.
+fn update_events(workspace: &Workspace,
+                 cargo_args: &CargoArgs,
+                 mut to_update: Vec<&Event>)
+                 -> SnapResult<()> {
+    let keys: Vec<&str> = to_update.iter().map(|event| event.key.as_str()).collect();
+    println!("Updating {}...", keys.join(", "));
+
+    let filter = keys.join(",");
+    let mut written = BTreeSet::new();
+    for selection in select_tests(workspace, &to_update) {
+        let run = run_cargo_test(workspace,
+                                 &selection.cargo_args(cargo_args),
+                                 &[(UPDATE_SNAPSHOTS_VAR, "always"),
+                                   (UPDATE_SNAPSHOTS_FILTER_VAR, &filter)],
+                                 false)?;
+
+        print_unrelated_failures(&unrelated_failures(&run.suites(), &run.events));
+
+        written.extend(run.events
+            .into_iter()
+            .filter(|event| event.outcome == events::Outcome::Written)
+            .map(|event| event.key));
+    }
+    to_update.retain(|event| !written.contains(&event.key));
 
-impl FnName {
-    // TODO this should let the user see why the test failed, or warn them if it wasn't from
-    // snapshot failure
-    fn success(&self) -> SnapResult<bool> {
-        Ok(cmd("cargo", &["test", &self.0])
-               .run()
-               .chain_err(|| "unable to execute cargo")?
-               .status
-               .success())
+    if !to_update.is_empty() {
+        let keys: Vec<&str> = to_update.iter().map(|event| event.key.as_str()).collect();
+        bail!("unable to update {}!", keys.join(", "));
     }
+    Ok(())
 }
 
-fn find_existing_snapshot_test_names() -> SnapResult<Vec<FnName>> {
-    let cwd = ::std::env::current_dir()
-        .chain_err(|| "unable to read cwd")?;
-
-    let mut existing: Vec<SnapFileContents> = Vec::new();
+/// Runs the test suite once, returning the last event of every snapshot that mismatched or is
+/// missing.
+fn find_failed_snapshots(workspace: &Workspace,
+                         packages: &[&Package],
+                         cargo_args: &CargoArgs)
+                         -> SnapResult<Vec<Event>> {
+    let all_args = cargo_args.with(&PackageSelection::cargo_args(packages), &[]);
+    let run = run_cargo_test(workspace, &all_args, &[], false)?;
+
+    let unrelated = unrelated_failures(&run.suites(), &run.events);
+    if !unrelated.is_empty() {
+        print_unrelated_failures(&unrelated);
+        println!("\nThese can't be fixed by updating snapshots, so they won't be offered for update.");
+    }
 
-    let snap_extension = OsStr::new("snap");
-    for walk_result in WalkDir::new(cwd) {
-        let entry = walk_result
-            .chain_err(|| "unable to traverse project directory")?;
+    Ok(failed_snapshots(run.events))
+}
 
-        if entry.path().extension() == Some(snap_extension) {
-            let rdr = BufReader::new(File::open(entry.path())
-                                         .chain_err(|| "unable to open snapshot file")?);
-            let contents = serde_json::from_reader(rdr)
-                .chain_err(|| "unable to parse snapshot file")?;
-            existing.push(contents);
-        }
-    }
+fn check(cwd: &Path, packages: &[&Package]) -> SnapResult<()> {
+    let mut problems = 0;
 
-    let mut test_function_names = Vec::new();
-    for snap_file in existing {
-        for fun in snap_file.keys() {
-            // now we need to strip crate name from the string
-            let mut test_fn_chunks = fun.splitn(2, "::");
+    for package in packages {
+        let layout = package.config()?.layout();
+        let found = SnapFiles::find(package.manifest_dir(), &layout)?;
 
-            match test_fn_chunks.next() {
-                Some(_) => (),
-                None => bail!("malformed test fn name: empty"),
+        for pending in &found.pending {
+            for key in pending.contents.keys() {
+                println!("pending review: {} in {}", key, relative_to(cwd, &pending.path));
+                problems += 1;
             }
+        }
 
-            let real_test_fn = match test_fn_chunks.next() {
-                Some(n) => n,
-                None => bail!("malformed test fn name: only had crate name"),
-            };
-
-            test_function_names.push(FnName(real_test_fn.to_owned()));
+        for snap_file in &found.snapshots {
+            for obsolete in find_obsolete(snap_file, &layout) {
+                let location = relative_to(cwd, &obsolete.snap_file);
+                match obsolete.key {
+                    Some(key) => println!("obsolete: {} in {}, {}", key, location, obsolete.reason),
+                    None => println!("obsolete: {}, {}", location, obsolete.reason),
+                }
+                problems += 1;
+            }
         }
     }
-    Ok(test_function_names)
-}
 
-enum TestFailureSelection {
-    Retry,
-    Skip,
-    Abort,
+    if problems > 0 {
+        bail!("{} snapshot problem(s) found, run `cargo snapshot update` and remove obsolete snapshots",
+              problems);
+    }
+
+    println!("All snapshots are up to date.");
+    Ok(())
 }
 
-fn capture_failure_selection() -> SnapResult<TestFailureSelection> {
-    let options = ["Retry", "Skip", "Abort"];
+fn test(workspace: &Workspace, cargo_args: &[String], junit: Option<&Path>) -> SnapResult<()> {
+    let run = run_cargo_test(workspace, cargo_args, &[], true)?;
+    let members: Vec<&Package> = workspace.members.iter().collect();
+    let recorded = recorded_keys(&members)?;
 
-    let selection = Select::new()
-        .items(&options)
-        .interact()
-        .chain_err(|| "unable to retrieve user input")?;
+    let suites = run.suites();
+    print_summary(&classify(&suites, &run.events, &recorded));
 
-    if options[selection] == "Retry" {
-        Ok(TestFailureSelection::Retry)
-    } else if options[selection] == "Skip" {
-        Ok(TestFailureSelection::Skip)
-    } else if options[selection] == "Abort" {
-        Ok(TestFailureSelection::Abort)
-    } else {
-        bail!("invalid menu selection")
+    if let Some(junit) = junit {
+        write_junit(junit, &suites, &run.events)?;
+    }
+
+    if !run.success {
+        bail!("`cargo test` failed");
     }
+    Ok(())
+}
+
+fn relative_to(base: &Path, path: &Path) -> String {
+    path.strip_prefix(base).unwrap_or(path).display().to_string()
 }
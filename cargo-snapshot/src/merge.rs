@@ -0,0 +1,146 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use snapshot::SnapFileContents;
+
+use crate::snap_files::SnapFile;
+use crate::{ResultExt, SnapResult};
+
+/// Merges snapshot files for git, which passes the common ancestor, our version and their
+/// version, and expects the result in place of ours.
+///
+/// Keys changed on only one side take that side's snapshot. When both sides changed a key
+/// differently, ours is kept unless we removed it, and the merge fails listing the conflicting
+/// keys.
+pub fn merge_driver(base: &Path, ours: &Path, theirs: &Path) -> SnapResult<()> {
+    let base_contents = read_contents(base)?;
+    let ours_contents = read_contents(ours)?;
+    let theirs_contents = read_contents(theirs)?;
+
+    let (merged, conflicts) = merge(base_contents, ours_contents, theirs_contents);
+
+    // keep the layout of our side, since that's what the package was configured to write
+    let pretty = fs::read_to_string(ours)
+        .chain_err(|| format!("unable to read {:?}", ours))?
+        .trim()
+        .contains('\n');
+    let serialized = if pretty {
+        serde_json::to_string_pretty(&merged)
+    } else {
+        serde_json::to_string(&merged)
+    }.chain_err(|| "unable to serialize merged snapshots")?;
+
+    fs::write(ours, serialized).chain_err(|| format!("unable to write {:?}", ours))?;
+
+    if !conflicts.is_empty() {
+        eprintln!("snapshots changed on both sides, keeping ours:");
+        for key in &conflicts {
+            eprintln!("    {}", key);
+        }
+        bail!("{} conflicting snapshot(s), rerun their tests and `cargo snapshot update` after \
+               resolving the code",
+              conflicts.len());
+    }
+    Ok(())
+}
+
+/// Git passes an empty file when a side doesn't have the file, like the ancestor of a file added
+/// on both branches.
+fn read_contents(path: &Path) -> SnapResult<SnapFileContents> {
+    let is_empty = fs::metadata(path)
+        .chain_err(|| format!("unable to read {:?}", path))?
+        .len() == 0;
+
+    if is_empty {
+        Ok(SnapFileContents::new())
+    } else {
+        Ok(SnapFile::read(path)?.contents)
+    }
+}
+
+/// Three-way merges snapshot files key by key, returning the merged contents and the keys which
+/// conflicted.
+pub fn merge(mut base: SnapFileContents,
+             mut ours: SnapFileContents,
+             mut theirs: SnapFileContents)
+             -> (SnapFileContents, Vec<String>) {
+    let keys: BTreeSet<String> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .cloned()
+        .collect();
+
+    let mut merged = SnapFileContents::new();
+    let mut conflicts = Vec::new();
+
+    for key in keys {
+        let base_entry = base.remove(&key);
+        let ours_entry = ours.remove(&key);
+        let theirs_entry = theirs.remove(&key);
+
+        let chosen = if ours_entry == theirs_entry || theirs_entry == base_entry {
+            ours_entry
+        } else if ours_entry == base_entry {
+            theirs_entry
+        } else {
+            conflicts.push(key.clone());
+            // a removal on our side conflicting with a change on theirs keeps their change
+            ours_entry.or(theirs_entry)
+        };
+
+        if let Some(snapshot) = chosen {
+            merged.insert(key, snapshot);
+        }
+    }
+
+    (merged, conflicts)
+}
+
+#[cfg(test)]
+mod merge_tests {
+    use super::merge;
+    use crate::test_support::contents;
+    use serde_json::Value;
+    use snapshot::SnapFileContents;
+
+    /// The entries' functions and values, leaving off the module every key starts with.
+    fn values(contents: &SnapFileContents) -> Vec<(&str, &Value)> {
+        contents
+            .iter()
+            .map(|(key, snapshot)| (key.trim_start_matches("simple::test::"), &snapshot.recorded_value))
+            .collect()
+    }
+
+    #[test]
+    fn it_should_merge_keys_changed_on_one_side() {
+        let base = contents(&[("same", 1), ("ours", 1), ("theirs", 1), ("removed", 1)]);
+        let ours = contents(&[("same", 1), ("ours", 2), ("theirs", 1), ("added", 5)]);
+        let theirs = contents(&[("same", 1), ("ours", 1), ("theirs", 3)]);
+
+        let (merged, conflicts) = merge(base, ours, theirs);
+
+        assert!(conflicts.is_empty());
+        assert_eq!(values(&merged),
+                   vec![("added", &Value::from(5)),
+                        ("ours", &Value::from(2)),
+                        ("same", &Value::from(1)),
+                        ("theirs", &Value::from(3))]);
+    }
+
+    #[test]
+    fn it_should_keep_ours_on_conflicts() {
+        let base = contents(&[("both", 1), ("gone", 1)]);
+        let ours = contents(&[("both", 2), ("new", 1)]);
+        let theirs = contents(&[("both", 3), ("gone", 4), ("new", 1)]);
+
+        let (merged, conflicts) = merge(base, ours, theirs);
+
+        assert_eq!(conflicts, vec!["simple::test::both", "simple::test::gone"]);
+        assert_eq!(values(&merged),
+                   vec![("both", &Value::from(2)),
+                        ("gone", &Value::from(4)),
+                        ("new", &Value::from(1))]);
+    }
+}
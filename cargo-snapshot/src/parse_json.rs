@@ -0,0 +1,283 @@
+use serde_derive::Deserialize;
+
+use crate::parse_tests::{handle_parsed_suite, panic_message, Failure, Suite, SuiteResult, Test};
+
+#[derive(Debug, Deserialize)]
+struct Event {
+    #[serde(rename = "type")]
+    kind: String,
+    event: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    stdout: Option<String>,
+    #[serde(default)]
+    test_count: i64,
+    #[serde(default)]
+    passed: i64,
+    #[serde(default)]
+    failed: i64,
+    #[serde(default)]
+    ignored: i64,
+    #[serde(default)]
+    measured: i64,
+}
+
+/// Splits the output of `cargo test -- --format json` into a suite per test executable as it
+/// arrives, turning libtest's events back into what it would have printed without `--format`.
+#[derive(Default)]
+pub struct JsonStream {
+    suites: Vec<JsonSuite>,
+    current: Option<JsonSuite>,
+}
+
+impl JsonStream {
+    /// Reads one line of output, returning the text to show for it.
+    pub fn push(&mut self, line: &str) -> String {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("Running ") || trimmed.starts_with("Doc-tests ") {
+            self.finish_suite();
+            let name = trimmed.split_once(' ').map_or("", |(_, name)| name.trim());
+            self.current = Some(JsonSuite::new(name.to_owned()));
+            return format!("{}\n", line);
+        }
+
+        match (self.current.as_mut(), serde_json::from_str::<Event>(line)) {
+            (Some(suite), Ok(event)) => suite.record(event),
+            // build output, or a test target without the libtest harness printing what it likes
+            _ => format!("{}\n", line),
+        }
+    }
+
+    /// The suites of every test executable that reported libtest's events.
+    pub fn finish(mut self) -> Vec<JsonSuite> {
+        self.finish_suite();
+        self.suites
+    }
+
+    fn finish_suite(&mut self) {
+        if let Some(mut suite) = self.current.take().filter(|suite| suite.saw_events) {
+            if !suite.finished {
+                // the executable stopped early, so count what it managed to report
+                suite.passed = suite.count("pass");
+                suite.failed = suite.count("fail");
+                suite.ignored = suite.count("ignored");
+            }
+            self.suites.push(suite);
+        }
+    }
+}
+
+/// The results of one test executable, read from libtest's `--format json` events.
+#[derive(Debug, PartialEq)]
+pub struct JsonSuite {
+    pub name: String,
+    finished: bool,
+    saw_events: bool,
+    state: &'static str,
+    passed: i64,
+    failed: i64,
+    ignored: i64,
+    measured: i64,
+    tests: Vec<(String, &'static str, Option<String>)>,
+}
+
+impl JsonSuite {
+    fn new(name: String) -> Self {
+        JsonSuite {
+            name,
+            finished: false,
+            saw_events: false,
+            state: "fail",
+            passed: 0,
+            failed: 0,
+            ignored: 0,
+            measured: 0,
+            tests: Vec::new(),
+        }
+    }
+
+    /// Adds an event to the suite, returning what libtest would have printed for it.
+    fn record(&mut self, event: Event) -> String {
+        self.saw_events = true;
+
+        match (event.kind.as_str(), event.event.as_str()) {
+            ("suite", "started") => {
+                let count = event.test_count;
+                format!("\nrunning {} test{}\n", count, if count == 1 { "" } else { "s" })
+            }
+            ("suite", "ok") | ("suite", "failed") => {
+                self.finished = true;
+                self.state = if event.event == "ok" { "pass" } else { "fail" };
+                self.passed = event.passed;
+                self.failed = event.failed;
+                self.ignored = event.ignored;
+                self.measured = event.measured;
+                self.result()
+            }
+            ("test", "ok") => {
+                let shown = format!("test {} ... ok\n", event.name);
+                self.tests.push((event.name, "pass", None));
+                shown
+            }
+            ("test", "ignored") => {
+                let shown = format!("test {} ... ignored\n", event.name);
+                self.tests.push((event.name, "ignored", None));
+                shown
+            }
+            ("test", "failed") | ("test", "timeout") => {
+                let shown = format!("test {} ... FAILED\n", event.name);
+                let error = panic_message(event.stdout.as_deref().unwrap_or_default());
+                self.tests.push((event.name, "fail", Some(error.to_owned())));
+                shown
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// The failures and totals libtest prints once a suite finishes.
+    fn result(&self) -> String {
+        let mut text = String::new();
+
+        let failures: Vec<_> = self
+            .tests
+            .iter()
+            .filter_map(|(name, _, error)| error.as_ref().map(|error| (name, error)))
+            .collect();
+        if !failures.is_empty() {
+            text.push_str("\nfailures:\n\n");
+            for (name, error) in failures {
+                text.push_str(&format!("---- {} stdout ----\n{}\n\n", name, error));
+            }
+        }
+
+        text.push_str(&format!("\ntest result: {}. {} passed; {} failed; {} ignored; {} measured\n\n",
+                               if self.state == "pass" { "ok" } else { "FAILED" },
+                               self.passed,
+                               self.failed,
+                               self.ignored,
+                               self.measured));
+        text
+    }
+
+    fn count(&self, status: &str) -> i64 {
+        self.tests.iter().filter(|(_, s, _)| *s == status).count() as i64
+    }
+
+    /// The same results the human-readable output parser would produce.
+    pub fn suite(&self) -> Suite<'_, '_, '_, '_, '_> {
+        let tests = self
+            .tests
+            .iter()
+            .map(|(name, status, _)| Test {
+                name,
+                status,
+                error: None,
+            })
+            .collect();
+
+        let failures = self
+            .tests
+            .iter()
+            .filter_map(|(name, _, error)| {
+                error.as_ref().map(|error| Failure {
+                    name,
+                    error,
+                })
+            })
+            .collect();
+
+        handle_parsed_suite(
+            &self.name,
+            tests,
+            Some(failures),
+            SuiteResult {
+                state: self.state,
+                passed: self.passed,
+                failed: self.failed,
+                ignored: self.ignored,
+                total: self.passed + self.failed + self.ignored,
+                measured: self.measured,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod json_tests {
+    use super::JsonStream;
+    use crate::parse_tests::{Suite, Test};
+
+    #[test]
+    fn it_should_map_events_to_suites() {
+        let output = r#"   Compiling snapshot v0.1.0 (/p)
+     Running tests/simple.rs (target/debug/deps/simple-f90ad51e6d4ff9c3)
+{ "type": "suite", "event": "started", "test_count": 3 }
+{ "type": "test", "event": "started", "name": "test::compound_snapshot" }
+{ "type": "test", "name": "test::compound_snapshot", "event": "failed", "stdout": "\nthread 'test::compound_snapshot' (16355) panicked at src/lib.rs:133:13:\nTest output doesn't match recorded snapshot!\n\nnote: run with `RUST_BACKTRACE=1` environment variable to display a backtrace\n" }
+{ "type": "test", "event": "started", "name": "test::simple_snapshot" }
+{ "type": "test", "name": "test::simple_snapshot", "event": "ok" }
+{ "type": "test", "name": "test::slow", "event": "ignored" }
+{ "type": "suite", "event": "failed", "passed": 1, "failed": 1, "ignored": 1, "measured": 0, "filtered_out": 0, "exec_time": 0.003 }
+     Running tests/no_harness.rs (target/debug/deps/no_harness-2c25f3ea4c0e3d3b)
+custom output"#;
+
+        let mut stream = JsonStream::default();
+        let shown: String = output.lines().map(|line| stream.push(line)).collect();
+        let suites = stream.finish();
+
+        assert_eq!(suites.len(), 1);
+        assert_eq!(
+            suites[0].suite(),
+            Suite {
+                name: "tests/simple.rs (target/debug/deps/simple-f90ad51e6d4ff9c3)",
+                state: "fail",
+                passed: 1,
+                failed: 1,
+                ignored: 1,
+                measured: 0,
+                total: 3,
+                tests: vec![
+                    Test {
+                        name: "test::compound_snapshot",
+                        status: "fail",
+                        error: Some("thread 'test::compound_snapshot' (16355) panicked at \
+                                     src/lib.rs:133:13:\nTest output doesn't match recorded \
+                                     snapshot!"),
+                    },
+                    Test {
+                        name: "test::simple_snapshot",
+                        status: "pass",
+                        error: None,
+                    },
+                    Test {
+                        name: "test::slow",
+                        status: "ignored",
+                        error: None,
+                    },
+                ],
+            }
+        );
+
+        assert!(shown.contains("running 3 tests\ntest test::compound_snapshot ... FAILED\n"));
+        assert!(shown.contains("---- test::compound_snapshot stdout ----\nthread"));
+        assert!(shown.contains(
+            "test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured\n"));
+        assert!(shown.ends_with("custom output\n"));
+    }
+
+    #[test]
+    fn it_should_count_tests_of_an_unfinished_suite() {
+        let mut stream = JsonStream::default();
+        for line in &["     Running tests/simple.rs (target/debug/deps/simple-f90ad51e6d4ff9c3)",
+                      r#"{ "type": "suite", "event": "started", "test_count": 2 }"#,
+                      r#"{ "type": "test", "name": "test::simple_snapshot", "event": "ok" }"#,
+                      "error: test failed, to rerun pass `--test simple`"] {
+            stream.push(line);
+        }
+
+        let suites = stream.finish();
+        let suite = suites[0].suite();
+        assert_eq!((suite.state, suite.passed, suite.total), ("fail", 1, 1));
+    }
+}
@@ -1,6 +1,6 @@
 use std::str;
 
-use nom::{line_ending, digit, space};
+use nom::{line_ending, digit, not_line_ending, IResult};
 
 named!(
     rest_of_line<&str>,
@@ -79,19 +79,31 @@ pub struct Test<'a, 'b, 'c> {
 }
 
 named!(
-    test_result<Test>,
+    test_status<&[u8], &str>,
+    alt!(
+        ok |
+        failed |
+        // newer versions can give a reason, like `ignored, slow`
+        value!("ignored", preceded!(tag!("ignored"), not_line_ending))
+    )
+);
+
+named!(
+    test_result<&[u8], Test<'_, '_, '_> >,
     do_parse!(
-        tag!("test") >>
-        space >>
-        name: map_res!(
-            take_until_s!(" ..."),
-            str::from_utf8
+        tag!("test ") >>
+        name: verify!(
+            map_res!(
+                take_until!(" ..."),
+                str::from_utf8
+            ),
+            |name: &str| !name.contains('\n')
         ) >>
         tag!(" ...") >>
-        status: ws!(ok_or_failed) >>
+        status: ws!(test_status) >>
         (Test {
-            name: name,
-            status: status,
+            name,
+            status,
             error: None
         })
     )
@@ -100,7 +112,7 @@ named!(
 named!(
     test_results<Vec<Test> >,
     many0!(
-        test_result
+        complete!(test_result)
     )
 );
 
@@ -139,6 +151,8 @@ named!(
         tag!("ignored;") >>
         measured: digits >>
         ws!(tag!("measured")) >>
+        // newer versions add `; N filtered out; finished in Xs`
+        opt!(complete!(preceded!(char!(';'), not_line_ending))) >>
         (SuiteResult {
           state:state,
           passed:passed,
@@ -170,16 +184,52 @@ pub struct Failure<'a, 'b> {
     pub error: &'b str,
 }
 
+named!(
+    note<()>,
+    do_parse!(
+        alt!(tag!("note: ") | tag!("stack backtrace:")) >>
+        ()
+    )
+);
+
+// A line of a failed test's output, up to the next failure or list of failed tests.
+named!(
+    output_line<()>,
+    do_parse!(
+        not!(fail_line) >>
+        not!(ws!(tag!("failures:"))) >>
+        not_line_ending >>
+        opt!(complete!(line_ending)) >>
+        ()
+    )
+);
+
+// A failed test's output up to any notes or backtrace libtest appended to the panic message.
+named!(
+    failure_message<&[u8], &str>,
+    map!(
+        map_res!(
+            recognize!(many0!(preceded!(not!(note), output_line))),
+            str::from_utf8
+        ),
+        str::trim
+    )
+);
+
+/// A failed test's captured output without the notes or backtrace libtest appends to panics.
+pub fn panic_message(output: &str) -> &str {
+    match failure_message(output.as_bytes()) {
+        IResult::Done(_, message) => message,
+        _ => output.trim(),
+    }
+}
+
 named!(
     failure<Failure>,
     do_parse!(
         name: fail_line >>
-        error: rest_of_line >>
-        opt!(
-            tag!("note: Run with `RUST_BACKTRACE=1` for a backtrace.")
-        ) >>
-        line_ending >>
-        line_ending >>
+        error: failure_message >>
+        many0!(output_line) >>
         (Failure {
             name:name,
             error:error
@@ -220,7 +270,7 @@ fn find_message_by_name<'a, 'b>(name: &str, failures: &Vec<Failure<'a, 'b>>) ->
     failures.iter().find(|x| x.name == name).map(|x| x.error)
 }
 
-fn handle_parsed_suite<'a, 'b, 'c, 'd, 'e>(name: &'a str,
+pub fn handle_parsed_suite<'a, 'b, 'c, 'd, 'e>(name: &'a str,
                                            tests: Vec<Test<'c, 'd, 'e>>,
                                            failures: Option<Vec<Failure<'e, 'e>>>,
                                            result: SuiteResult<'b>)
@@ -253,27 +303,64 @@ fn handle_parsed_suite<'a, 'b, 'c, 'd, 'e>(name: &'a str,
     }
 }
 
+/// The result of a suite whose test executable stopped before printing one, counted from the
+/// tests it reported.
+fn unfinished_result(tests: &[Test]) -> SuiteResult<'static> {
+    let count = |status| tests.iter().filter(|test| test.status == status).count() as i64;
+
+    SuiteResult {
+        state: "fail",
+        passed: count("pass"),
+        failed: count("fail"),
+        ignored: count("ignored"),
+        total: tests.len() as i64,
+        measured: 0,
+    }
+}
+
+// Skips lines cargo prints between suites, like warnings, up to the next suite header.
+named!(
+    skip_to_suite<()>,
+    do_parse!(
+        many0!(
+            do_parse!(
+                not!(suite_line) >>
+                take_until_and_consume!("\n") >>
+                ()
+            )
+        ) >>
+        ()
+    )
+);
+
 named!(
     suite_parser<Suite>,
     do_parse!(
+        skip_to_suite >>
         name: suite_line >>
         suite_count >>
         tests: test_results >>
         failures: fail_opt >>
-        result: suite_result >>
-        (handle_parsed_suite(name, tests, failures, result))
+        // missing if the test executable crashed or was killed
+        result: opt!(complete!(suite_result)) >>
+        ({
+            let result = result.unwrap_or_else(|| unfinished_result(&tests));
+            handle_parsed_suite(name, tests, failures, result)
+        })
     )
 );
 
 named!(
     suites_parser<Vec<Suite > >,
-    many1!(suite_parser)
+    many0!(complete!(suite_parser))
 );
 
 named!(
     pub cargo_test_result_parser<Vec<Suite > >,
     do_parse!(
         compiling >>
+        // warnings and other build output
+        take_until!("Finished") >>
         finished >>
         suites: suites_parser >>
         (suites)
@@ -288,6 +375,7 @@ mod parser_tests {
     use super::{compiling, finished, suite_line, suite_count, ok_or_failed, Test, test_result,
                 test_results, digits, suite_result, SuiteResult, cargo_test_result_parser, Suite,
                 fail_line, failure, Failure, failures};
+    use crate::test_run::parse_output;
 
     fn assert_done<R: PartialEq + Debug>(l: IResult<&[u8], R>, r: R) {
         assert_eq!(
@@ -561,6 +649,152 @@ error: test failed";
         );
     }
 
+    #[test]
+    fn test_current_libtest_output() {
+        let output = b"   Compiling snapshot v0.1.0 (/root/crate)
+warning: unused variable: `x`
+    Finished `test` profile [unoptimized + debuginfo] target(s) in 1.35s
+warning: the following packages contain code that will be rejected by a future version of Rust
+     Running tests/simple.rs (target/debug/deps/simple-f90ad51e6d4ff9c3)
+
+running 3 tests
+test test::compound_snapshot ... FAILED
+test test::ignored_snapshot ... ignored, slow
+test test::simple_snapshot ... ok
+
+failures:
+
+---- test::compound_snapshot stdout ----
+
+thread 'test::compound_snapshot' (14975) panicked at src/lib.rs:133:13:
+Test output doesn't match recorded snapshot!
+  ~ .z: \"woo\" -> \"woowwowow\"
+
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+
+failures:
+    test::compound_snapshot
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+error: test failed, to rerun pass `--test simple`
+";
+
+        let suites = match cargo_test_result_parser(output) {
+            IResult::Done(_, suites) => suites,
+            other => panic!("unable to parse: {:?}", other),
+        };
+
+        assert_eq!(
+            suites,
+            vec![Suite {
+                name: "tests/simple.rs (target/debug/deps/simple-f90ad51e6d4ff9c3)",
+                state: "fail",
+                passed: 1,
+                failed: 1,
+                ignored: 1,
+                measured: 0,
+                total: 3,
+                tests: vec![
+                    Test {
+                        name: "test::compound_snapshot",
+                        status: "fail",
+                        error: Some("thread 'test::compound_snapshot' (14975) panicked at \
+                                     src/lib.rs:133:13:\nTest output doesn't match recorded \
+                                     snapshot!\n  ~ .z: \"woo\" -> \"woowwowow\""),
+                    },
+                    Test {
+                        name: "test::ignored_snapshot",
+                        status: "ignored",
+                        error: None,
+                    },
+                    Test {
+                        name: "test::simple_snapshot",
+                        status: "pass",
+                        error: None,
+                    },
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_compile_error() {
+        let output = "   Compiling scratch v0.1.0 (/tmp/scratch)
+error: this file contains an unclosed delimiter
+  --> src/lib.rs:15:9
+   |
+15 | fn x( {
+   |     - -^
+   |     | |
+   |     | unclosed delimiter
+   |     unclosed delimiter
+
+error: could not compile `scratch` (lib) due to 1 previous error
+";
+
+        assert_eq!(parse_output(output), vec![]);
+    }
+
+    #[test]
+    fn test_aborted_test_binary() {
+        let output = "   Compiling scratch v0.1.0 (/tmp/scratch)
+    Finished `test` profile [unoptimized + debuginfo] target(s) in 0.81s
+     Running tests/b.rs (target/debug/deps/b-22827d091beec243)
+
+running 3 tests
+test fine ... ok
+test aborts ... error: test failed, to rerun pass `--test b`
+
+Caused by:
+  process didn't exit successfully: `/tmp/scratch/target/debug/deps/b-22827d091beec243` (signal: 6, SIGABRT: process abort signal)
+     Running tests/c.rs (target/debug/deps/c-9bcac8ef5612c071)
+
+running 1 test
+test later ... ok
+
+test result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+error: 1 target failed:
+    `--test b`
+";
+
+        assert_eq!(
+            parse_output(output),
+            vec![
+                Suite {
+                    name: "tests/b.rs (target/debug/deps/b-22827d091beec243)",
+                    state: "fail",
+                    passed: 1,
+                    failed: 0,
+                    ignored: 0,
+                    measured: 0,
+                    total: 1,
+                    tests: vec![Test {
+                        name: "fine",
+                        status: "pass",
+                        error: None,
+                    }],
+                },
+                Suite {
+                    name: "tests/c.rs (target/debug/deps/c-9bcac8ef5612c071)",
+                    state: "pass",
+                    passed: 1,
+                    failed: 0,
+                    ignored: 0,
+                    measured: 0,
+                    total: 1,
+                    tests: vec![Test {
+                        name: "later",
+                        status: "pass",
+                        error: None,
+                    }],
+                },
+            ]
+        );
+    }
+
     #[test]
     fn test_success_run() {
         let output = b"   Compiling rustc-serialize v0.3.22
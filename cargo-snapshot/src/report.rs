@@ -0,0 +1,342 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use snapshot::diff;
+use snapshot::events::{self, Event};
+
+use crate::cargo_args::CargoArgs;
+use crate::show::render_value;
+use crate::snap_files::SnapFiles;
+use crate::test_run::{failed_snapshots, run_cargo_test};
+use crate::workspace::{Package, PackageSelection, Workspace};
+use crate::{relative_to, ResultExt, SnapResult};
+
+/// A snapshot that needs review, with its recorded and new values.
+pub struct ReportEntry {
+    pub key: String,
+    pub package: String,
+    /// The `.snap` file, relative to the current directory.
+    pub snap_file: String,
+    pub status: &'static str,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// A row of a side-by-side diff, with the recorded line on the left and the new one on the right.
+#[derive(Debug, PartialEq)]
+pub enum Row<'a> {
+    Same(&'a str),
+    Changed(&'a str, &'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Runs the tests and writes an HTML page to `out_dir` showing every snapshot that mismatched,
+/// was missing or is pending review. Fails if the tests did, after writing the report.
+pub fn report(workspace: &Workspace,
+              packages: &[&Package],
+              cargo_args: &CargoArgs,
+              out_dir: &Path,
+              cwd: &Path)
+              -> SnapResult<()> {
+    let all_args = cargo_args.with(&PackageSelection::cargo_args(packages), &[]);
+    let run = run_cargo_test(workspace, &all_args, &[], true)?;
+
+    let entries = collect_entries(packages, &failed_snapshots(run.events), cwd)?;
+
+    fs::create_dir_all(out_dir).chain_err(|| format!("unable to create {:?}", out_dir))?;
+    let page = out_dir.join("index.html");
+    fs::write(&page, render_html(&entries)?)
+        .chain_err(|| format!("unable to write {:?}", page))?;
+    println!("\nWrote {} snapshot(s) needing review to {}", entries.len(), relative_to(cwd, &page));
+
+    if !run.success {
+        bail!("`cargo test` failed");
+    }
+    Ok(())
+}
+
+/// Pairs each failed snapshot from the run, and each pending one, with its recorded value.
+fn collect_entries(packages: &[&Package],
+                   failed: &[Event],
+                   cwd: &Path)
+                   -> SnapResult<Vec<ReportEntry>> {
+    let mut entries = Vec::new();
+
+    for package in packages {
+        let found = SnapFiles::find(package.manifest_dir(), &package.config()?.layout())?;
+        let recorded = |snap_path: &Path, key: &str| {
+            found
+                .snapshots
+                .iter()
+                .find(|snap_file| snap_file.path == snap_path)
+                .and_then(|snap_file| snap_file.contents.get(key))
+                .map(|snapshot| snapshot.recorded_value.clone())
+        };
+
+        let mut seen: BTreeSet<(PathBuf, String)> = BTreeSet::new();
+        for event in failed.iter().filter(|e| e.manifest_dir == package.manifest_dir()) {
+            let snap_path = package.manifest_dir().join(&event.file);
+            entries.push(ReportEntry {
+                key: event.key.clone(),
+                package: package.name.clone(),
+                snap_file: relative_to(cwd, &snap_path),
+                status: match event.outcome {
+                    events::Outcome::Missing => "missing",
+                    _ => "mismatched",
+                },
+                old: recorded(&snap_path, &event.key),
+                new: event.new_value.clone(),
+            });
+            seen.insert((snap_path, event.key.clone()));
+        }
+
+        for pending in &found.pending {
+            // `<file>.snap.pending` reviews `<file>.snap`
+            let snap_path = pending.path.with_extension("");
+            for (key, snapshot) in &pending.contents {
+                if seen.contains(&(snap_path.clone(), key.clone())) {
+                    continue;
+                }
+
+                entries.push(ReportEntry {
+                    key: key.clone(),
+                    package: package.name.clone(),
+                    snap_file: relative_to(cwd, &snap_path),
+                    status: "pending",
+                    old: recorded(&snap_path, key),
+                    new: Some(snapshot.recorded_value.clone()),
+                });
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Lines up two texts by their longest common subsequence of lines, pairing up lines replaced
+/// between matching ones.
+pub fn align_lines<'a>(old: &'a str, new: &'a str) -> Vec<Row<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // most changes touch a few lines of a big value, so only the middle needs searching
+    let prefix = old.iter().zip(&new).take_while(|(o, n)| o == n).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+
+    let mut matches: Vec<(usize, usize)> = (0..prefix).map(|k| (k, k)).collect();
+    common_lines(&old[prefix..old.len() - suffix],
+                 &new[prefix..new.len() - suffix],
+                 (prefix, prefix),
+                 &mut matches);
+    matches.extend((0..suffix).map(|k| (old.len() - suffix + k, new.len() - suffix + k)));
+
+    let mut rows = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (matched_old, matched_new) in matches {
+        push_replaced(&mut rows, &old[i..matched_old], &new[j..matched_new]);
+        rows.push(Row::Same(old[matched_old]));
+        i = matched_old + 1;
+        j = matched_new + 1;
+    }
+    push_replaced(&mut rows, &old[i..], &new[j..]);
+
+    rows
+}
+
+/// Appends where the lines of a longest common subsequence of `old` and `new` are, offset by
+/// `start`. Uses Hirschberg's algorithm, so memory stays linear in the number of lines.
+fn common_lines(old: &[&str], new: &[&str], start: (usize, usize), matches: &mut Vec<(usize, usize)>) {
+    if old.is_empty() || new.is_empty() {
+        return;
+    }
+    if old.len() == 1 {
+        if let Some(j) = new.iter().position(|line| *line == old[0]) {
+            matches.push((start.0, start.1 + j));
+        }
+        return;
+    }
+
+    // split `new` where the halves of `old` have the most lines in common with its two parts
+    let mid = old.len() / 2;
+    let forward = common_lengths(old[..mid].iter().copied(), new);
+    let reversed: Vec<&str> = new.iter().rev().copied().collect();
+    let backward = common_lengths(old[mid..].iter().rev().copied(), &reversed);
+    let split = (0..=new.len())
+        .max_by_key(|&j| forward[j] + backward[new.len() - j])
+        .unwrap_or(0);
+
+    common_lines(&old[..mid], &new[..split], start, matches);
+    common_lines(&old[mid..], &new[split..], (start.0 + mid, start.1 + split), matches);
+}
+
+/// The length of the longest common subsequence of `old` and each prefix of `new`.
+fn common_lengths<'a>(old: impl Iterator<Item = &'a str>, new: &[&str]) -> Vec<usize> {
+    let mut lengths = vec![0; new.len() + 1];
+    for line in old {
+        let mut diagonal = 0;
+        for j in 0..new.len() {
+            let above = lengths[j + 1];
+            lengths[j + 1] = if line == new[j] { diagonal + 1 } else { above.max(lengths[j]) };
+            diagonal = above;
+        }
+    }
+    lengths
+}
+
+fn push_replaced<'a>(rows: &mut Vec<Row<'a>>, removed: &[&'a str], added: &[&'a str]) {
+    let longest = removed.len().max(added.len());
+    for idx in 0..longest {
+        rows.push(match (removed.get(idx), added.get(idx)) {
+            (Some(old), Some(new)) => Row::Changed(old, new),
+            (Some(old), None) => Row::Removed(old),
+            (None, Some(new)) => Row::Added(new),
+            (None, None) => unreachable!(),
+        });
+    }
+}
+
+fn render_html(entries: &[ReportEntry]) -> SnapResult<String> {
+    let mut html = String::from(HEADER);
+
+    if entries.is_empty() {
+        html.push_str("<p>No snapshots need review.</p>\n");
+    } else {
+        html.push_str(&format!("<p>{} snapshot(s) need review.</p>\n", entries.len()));
+    }
+
+    let mut by_file: BTreeMap<(&str, &str), Vec<&ReportEntry>> = BTreeMap::new();
+    for entry in entries {
+        by_file.entry((&entry.package, &entry.snap_file)).or_default().push(entry);
+    }
+
+    for ((package, snap_file), entries) in by_file {
+        html.push_str(&format!("<h2>{} <small>{}</small></h2>\n",
+                               escape(snap_file),
+                               escape(package)));
+
+        for entry in entries {
+            html.push_str(&format!("<section>\n<h3><code>{}</code> <span class=\"status {}\">{}</span></h3>\n",
+                                   escape(&entry.key),
+                                   entry.status,
+                                   entry.status));
+
+            if let (Some(old), Some(new)) = (&entry.old, &entry.new) {
+                html.push_str("<ul class=\"changes\">\n");
+                for change in diff::diff_values(old, new) {
+                    html.push_str(&format!("<li><code>{}</code></li>\n", escape(&change.to_string())));
+                }
+                html.push_str("</ul>\n");
+            }
+
+            let old = match entry.old {
+                Some(ref old) => render_value(old)?,
+                None => String::new(),
+            };
+            let new = match entry.new {
+                Some(ref new) => render_value(new)?,
+                None => String::new(),
+            };
+
+            html.push_str("<table>\n<tr><th>Recorded</th><th>New</th></tr>\n");
+            for row in align_lines(&old, &new) {
+                let (class, left, right) = match row {
+                    Row::Same(line) => ("same", line, line),
+                    Row::Changed(left, right) => ("changed", left, right),
+                    Row::Removed(left) => ("removed", left, ""),
+                    Row::Added(right) => ("added", "", right),
+                };
+                html.push_str(&format!("<tr class=\"{}\"><td>{}</td><td>{}</td></tr>\n",
+                                       class,
+                                       escape(left),
+                                       escape(right)));
+            }
+            html.push_str("</table>\n</section>\n");
+        }
+    }
+
+    html.push_str("</body>\n</html>\n");
+    Ok(html)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+static HEADER: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Snapshot report</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+h2 small { color: #666; font-weight: normal; }
+section { margin-bottom: 2em; }
+.status { font-size: 0.8em; padding: 0.1em 0.4em; border-radius: 0.3em; background: #eee; }
+.status.mismatched { background: #fdd; }
+.status.missing { background: #ffd; }
+.status.pending { background: #def; }
+table { border-collapse: collapse; width: 100%; table-layout: fixed; }
+th { text-align: left; }
+td { font-family: monospace; white-space: pre-wrap; vertical-align: top; padding: 0 0.5em; }
+tr.changed td:first-child, tr.removed td:first-child { background: #fdd; }
+tr.changed td:last-child, tr.added td:last-child { background: #dfd; }
+</style>
+</head>
+<body>
+<h1>Snapshot report</h1>
+"#;
+
+#[cfg(test)]
+mod report_tests {
+    use super::{align_lines, escape, Row};
+
+    #[test]
+    fn it_should_align_lines_side_by_side() {
+        let old = "{\n  \"a\": 1,\n  \"b\": 2,\n  \"c\": 3\n}";
+        let new = "{\n  \"a\": 1,\n  \"b\": 5,\n  \"c\": 3,\n  \"d\": 4\n}";
+
+        assert_eq!(align_lines(old, new),
+                   vec![Row::Same("{"),
+                        Row::Same("  \"a\": 1,"),
+                        Row::Changed("  \"b\": 2,", "  \"b\": 5,"),
+                        Row::Changed("  \"c\": 3", "  \"c\": 3,"),
+                        Row::Added("  \"d\": 4"),
+                        Row::Same("}")]);
+
+        assert_eq!(align_lines("", "new"), vec![Row::Added("new")]);
+        assert_eq!(align_lines("old", ""), vec![Row::Removed("old")]);
+    }
+
+    #[test]
+    fn it_should_align_lines_between_changes() {
+        assert_eq!(align_lines("a\nb\nc\nd\ne", "x\nb\nd\ny\ne"),
+                   vec![Row::Changed("a", "x"),
+                        Row::Same("b"),
+                        Row::Removed("c"),
+                        Row::Same("d"),
+                        Row::Added("y"),
+                        Row::Same("e")]);
+
+        let old: String = (0..100_000).map(|n| format!("{}\n", n)).collect();
+        let new = old.replacen("50000\n", "changed\n", 1);
+        let rows = align_lines(&old, &new);
+        assert_eq!(rows.len(), 100_000);
+        assert_eq!(rows[50_000], Row::Changed("50000", "changed"));
+    }
+
+    #[test]
+    fn it_should_escape_html() {
+        assert_eq!(escape("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+}
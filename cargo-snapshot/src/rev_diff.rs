@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use duct::cmd;
+use snapshot::diff::{self, Change};
+use snapshot::SnapFileContents;
+
+use crate::snap_files::SnapFiles;
+use crate::workspace::{Package, Workspace};
+use crate::{ResultExt, SnapResult};
+
+/// Snapshot files keyed by their path relative to the root of the git repository.
+type SnapTree = BTreeMap<PathBuf, SnapFileContents>;
+
+/// How the snapshots in one file differ between a revision and the working tree.
+#[derive(Debug, PartialEq)]
+pub struct FileDiff {
+    pub path: PathBuf,
+    pub entries: Vec<EntryDiff>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EntryDiff {
+    Added(String),
+    Removed(String),
+    Changed(String, Vec<Change>),
+}
+
+/// Prints how the snapshots of `packages` in the working tree differ from those at `rev`.
+pub fn diff_against(workspace: &Workspace, packages: &[&Package], rev: &str) -> SnapResult<()> {
+    let repo_root = git(&["rev-parse", "--show-toplevel"], Path::new("."))?;
+    let repo_root = PathBuf::from(String::from_utf8_lossy(&repo_root).trim());
+
+    let mut old = SnapTree::new();
+    let mut new = SnapTree::new();
+    for package in packages {
+        let layout = package.config()?.layout();
+
+        for snap_file in SnapFiles::find(package.manifest_dir(), &layout)?.snapshots {
+            let path = snap_file.path.strip_prefix(&repo_root).unwrap_or(&snap_file.path);
+            new.insert(path.to_owned(), snap_file.contents);
+        }
+
+        for path in files_at_rev(&repo_root, package.manifest_dir(), rev)? {
+            let absolute = repo_root.join(&path);
+            let in_package = workspace
+                .package_containing(&absolute)
+                .is_some_and(|owner| owner.manifest_dir() == package.manifest_dir());
+            if !in_package || !layout.is_snap_file(&absolute) {
+                continue;
+            }
+
+            let spec = format!("{}:{}", rev, path.display());
+            let contents = git(&["show", &spec], &repo_root)?;
+            let contents = serde_json::from_slice(&contents)
+                .chain_err(|| format!("unable to parse snapshot file {}", spec))?;
+            old.insert(path, contents);
+        }
+    }
+
+    let diffs = compare(&old, &new);
+    print_diffs(&diffs, rev);
+    Ok(())
+}
+
+/// Every file under `dir` at `rev`, relative to the root of the repository.
+fn files_at_rev(repo_root: &Path, dir: &Path, rev: &str) -> SnapResult<Vec<PathBuf>> {
+    let dir = dir.strip_prefix(repo_root).unwrap_or(dir);
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+
+    let listing = git(&["ls-tree", "-r", "--name-only", "--full-tree", rev, "--",
+                        &dir.to_string_lossy()],
+                      repo_root)?;
+
+    Ok(String::from_utf8_lossy(&listing)
+        .lines()
+        .map(PathBuf::from)
+        .collect())
+}
+
+fn git(args: &[&str], dir: &Path) -> SnapResult<Vec<u8>> {
+    let output = cmd("git", args)
+        .dir(dir)
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .chain_err(|| "unable to execute `git`")?;
+
+    if !output.status.success() {
+        bail!("`git {}` failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
+
+/// Compares snapshot files key by key, ignoring how their JSON happens to be laid out.
+pub fn compare(old: &SnapTree, new: &SnapTree) -> Vec<FileDiff> {
+    let empty = SnapFileContents::new();
+    let paths: BTreeSet<&PathBuf> = old.keys().chain(new.keys()).collect();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let old_file = old.get(path).unwrap_or(&empty);
+            let new_file = new.get(path).unwrap_or(&empty);
+            let keys: BTreeSet<&String> = old_file.keys().chain(new_file.keys()).collect();
+
+            let entries: Vec<EntryDiff> = keys
+                .into_iter()
+                .filter_map(|key| match (old_file.get(key), new_file.get(key)) {
+                    (Some(old), Some(new)) => {
+                        let changes = diff::diff_values(&old.recorded_value, &new.recorded_value);
+                        if changes.is_empty() {
+                            None
+                        } else {
+                            Some(EntryDiff::Changed(key.clone(), changes))
+                        }
+                    }
+                    (Some(_), None) => Some(EntryDiff::Removed(key.clone())),
+                    (None, Some(_)) => Some(EntryDiff::Added(key.clone())),
+                    (None, None) => None,
+                })
+                .collect();
+
+            if entries.is_empty() {
+                None
+            } else {
+                Some(FileDiff {
+                    path: path.clone(),
+                    entries,
+                })
+            }
+        })
+        .collect()
+}
+
+fn print_diffs(diffs: &[FileDiff], rev: &str) {
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+
+    for file_diff in diffs {
+        println!("{}", file_diff.path.display());
+        for entry in &file_diff.entries {
+            match entry {
+                EntryDiff::Added(key) => {
+                    println!("  + {}", key);
+                    added += 1;
+                }
+                EntryDiff::Removed(key) => {
+                    println!("  - {}", key);
+                    removed += 1;
+                }
+                EntryDiff::Changed(key, changes) => {
+                    println!("  ~ {}", key);
+                    for change in changes {
+                        println!("      {}", change);
+                    }
+                    changed += 1;
+                }
+            }
+        }
+    }
+
+    if diffs.is_empty() {
+        println!("No snapshots differ from {}.", rev);
+    } else {
+        println!("\n{} added, {} removed, {} changed since {}", added, removed, changed, rev);
+    }
+}
+
+#[cfg(test)]
+mod rev_diff_tests {
+    use super::{compare, EntryDiff, SnapTree};
+    use crate::test_support::contents;
+    use serde_json::Value;
+    use std::path::PathBuf;
+
+    #[test]
+    fn it_should_compare_snapshots_by_key() {
+        let path = PathBuf::from("tests/__snapshots__/simple.rs.snap");
+        let gone = PathBuf::from("tests/__snapshots__/gone.rs.snap");
+
+        let mut old = SnapTree::new();
+        old.insert(path.clone(),
+                   contents(&[("kept", Value::from(1)), ("changed", Value::from(vec![1, 2])),
+                              ("removed", Value::from(3))]));
+        old.insert(gone.clone(), contents(&[("only", Value::from(4))]));
+
+        let mut new = SnapTree::new();
+        new.insert(path.clone(),
+                   contents(&[("kept", Value::from(1)), ("changed", Value::from(vec![1, 5])),
+                              ("added", Value::from(6))]));
+
+        let diffs = compare(&old, &new);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].path, gone);
+        assert_eq!(diffs[0].entries, vec![EntryDiff::Removed("simple::test::only".to_owned())]);
+
+        assert_eq!(diffs[1].path, path);
+        let summary: Vec<String> = diffs[1]
+            .entries
+            .iter()
+            .map(|entry| match entry {
+                EntryDiff::Added(key) => format!("+ {}", key),
+                EntryDiff::Removed(key) => format!("- {}", key),
+                EntryDiff::Changed(key, changes) => format!("~ {} {}", key, changes[0]),
+            })
+            .collect();
+        assert_eq!(summary,
+                   vec!["+ simple::test::added",
+                        "~ simple::test::changed ~ [1]: 2 -> 5",
+                        "- simple::test::removed"]);
+    }
+}
@@ -0,0 +1,618 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use console::{Key, Style, Term};
+use fs2::FileExt;
+use serde_json::Value;
+use snapshot::config::{Format, Layout};
+use snapshot::events::{self, Event};
+use snapshot::{SnapFileContents, Snapshot};
+
+use crate::report::{align_lines, Row};
+use crate::show::render_value;
+use crate::snap_files::SnapFiles;
+use crate::workspace::Package;
+use crate::{relative_to, ResultExt, SnapResult};
+
+/// A new value waiting for the user to accept or reject it.
+#[derive(Debug)]
+pub struct ReviewItem {
+    pub key: String,
+    /// The `.snap` file the value is written to if it's accepted.
+    pub snap_file: PathBuf,
+    /// The `.snap` file relative to the current directory, for display.
+    pub location: String,
+    /// The test's source file, relative to its package.
+    pub source_file: PathBuf,
+    pub status: &'static str,
+    pub old: Option<Value>,
+    pub new: Value,
+    /// Whether a pending file holds the value, which is cleared once it's decided.
+    pub pending: bool,
+    pub format: Format,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decision {
+    Accept,
+    Reject,
+}
+
+/// A step to undo: the item selected when it was taken, and the items it decided with their
+/// decisions before it.
+type UndoStep = (usize, Vec<(usize, Option<Decision>)>);
+
+/// A line of an item's diff and how to style it.
+type DiffLine = (Style, String);
+
+/// The state of a review: a decision for each item, and what to restore on undo.
+pub struct Review<'a> {
+    pub items: &'a [ReviewItem],
+    pub decisions: Vec<Option<Decision>>,
+    /// The steps to undo, most recent last.
+    undo: Vec<UndoStep>,
+    pub selected: usize,
+    /// How many lines of the selected item's diff are scrolled past.
+    pub scroll: usize,
+    /// The diff of each item shown so far, so redrawing doesn't align its values again.
+    diffs: Vec<Option<Vec<DiffLine>>>,
+}
+
+/// Collects the values to review: the failed snapshots of a run, and everything in the pending
+/// files of `packages`.
+pub fn review_items(packages: &[&Package],
+                    failed: &[Event],
+                    cwd: &Path)
+                    -> SnapResult<Vec<ReviewItem>> {
+    let mut items: Vec<ReviewItem> = Vec::new();
+
+    for package in packages {
+        let config = package.config()?;
+        let layout = config.layout();
+        let found = SnapFiles::find(package.manifest_dir(), &layout)?;
+        let recorded = |snap_path: &Path, key: &str| {
+            found
+                .snapshots
+                .iter()
+                .find(|snap_file| snap_file.path == snap_path)
+                .and_then(|snap_file| snap_file.contents.get(key))
+                .map(|snapshot| snapshot.recorded_value.clone())
+        };
+
+        for event in failed.iter().filter(|e| e.manifest_dir == package.manifest_dir()) {
+            let new = match event.new_value {
+                Some(ref new) => new.clone(),
+                None => continue,
+            };
+            let snap_path = package.manifest_dir().join(&event.file);
+
+            items.push(ReviewItem {
+                key: event.key.clone(),
+                location: relative_to(cwd, &snap_path),
+                old: recorded(&snap_path, &event.key),
+                pending: false,
+                snap_file: snap_path,
+                source_file: event.source_file.clone(),
+                status: match event.outcome {
+                    events::Outcome::Missing => "missing",
+                    _ => "mismatched",
+                },
+                new,
+                format: config.format,
+            });
+        }
+
+        for pending in &found.pending {
+            // `<file>.snap.pending` reviews `<file>.snap`
+            let snap_path = pending.path.with_extension("");
+            for (key, snapshot) in &pending.contents {
+                let existing = items
+                    .iter_mut()
+                    .find(|item| item.snap_file == snap_path && item.key == *key);
+                if let Some(item) = existing {
+                    // the run's value is newer, but deciding on it settles the pending one too
+                    item.pending = true;
+                    continue;
+                }
+
+                items.push(ReviewItem {
+                    key: key.clone(),
+                    location: relative_to(cwd, &snap_path),
+                    old: recorded(&snap_path, key),
+                    pending: true,
+                    snap_file: snap_path.clone(),
+                    source_file: snapshot.file.iter().collect(),
+                    status: "pending",
+                    new: snapshot.recorded_value.clone(),
+                    format: config.format,
+                });
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+impl<'a> Review<'a> {
+    pub fn new(items: &'a [ReviewItem]) -> Self {
+        Review {
+            items,
+            decisions: vec![None; items.len()],
+            undo: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            diffs: vec![None; items.len()],
+        }
+    }
+
+    pub fn decide(&mut self, decision: Decision) {
+        let selected = self.selected;
+        self.record(&[selected], decision);
+        self.advance();
+    }
+
+    /// Accepts every item written to the same `.snap` file as the selected one.
+    pub fn accept_file(&mut self) {
+        let snap_file = &self.items[self.selected].snap_file;
+        let in_file: Vec<usize> = (0..self.items.len())
+            .filter(|&idx| self.items[idx].snap_file == *snap_file)
+            .collect();
+        self.record(&in_file, Decision::Accept);
+        self.advance();
+    }
+
+    /// Leaves the selected item undecided for now.
+    pub fn skip(&mut self) {
+        self.move_by(1);
+    }
+
+    /// Reverts the last decision, selecting the item it was made on.
+    pub fn undo(&mut self) {
+        if let Some((selected, step)) = self.undo.pop() {
+            for (idx, decision) in step {
+                self.decisions[idx] = decision;
+            }
+            self.selected = selected;
+            self.scroll = 0;
+        }
+    }
+
+    pub fn move_by(&mut self, delta: isize) {
+        let last = self.items.len().saturating_sub(1) as isize;
+        self.selected = (self.selected as isize + delta).clamp(0, last) as usize;
+        self.scroll = 0;
+    }
+
+    pub fn scroll_by(&mut self, delta: isize) {
+        self.scroll = (self.scroll as isize + delta).max(0) as usize;
+    }
+
+    pub fn decided(&self) -> usize {
+        self.decisions.iter().filter(|decision| decision.is_some()).count()
+    }
+
+    /// Works out the selected item's diff the first time it's shown.
+    pub fn load_diff(&mut self) -> SnapResult<()> {
+        if self.diffs[self.selected].is_none() {
+            self.diffs[self.selected] = Some(diff_lines(&self.items[self.selected])?);
+        }
+        Ok(())
+    }
+
+    /// The selected item's diff, empty until it's loaded.
+    fn diff(&self) -> &[DiffLine] {
+        self.diffs[self.selected].as_deref().unwrap_or_default()
+    }
+
+    fn record(&mut self, indices: &[usize], decision: Decision) {
+        let before = indices.iter().map(|&idx| (idx, self.decisions[idx])).collect();
+        self.undo.push((self.selected, before));
+        for &idx in indices {
+            self.decisions[idx] = Some(decision);
+        }
+    }
+
+    /// Selects the next undecided item, wrapping around, or stays put if they're all decided.
+    fn advance(&mut self) {
+        let count = self.items.len();
+        if let Some(next) = (1..=count)
+            .map(|offset| (self.selected + offset) % count)
+            .find(|&idx| self.decisions[idx].is_none())
+        {
+            self.selected = next;
+        }
+        self.scroll = 0;
+    }
+}
+
+/// Shows a full screen review of `items`, returning the decisions once the user applies them, or
+/// `None` if they quit without applying.
+pub fn review(items: &[ReviewItem]) -> SnapResult<Option<Vec<Option<Decision>>>> {
+    let term = Term::stdout();
+    let _screen = AlternateScreen::enter(&term)?;
+    let mut review = Review::new(items);
+
+    loop {
+        let (rows, cols) = term.size();
+        review.load_diff()?;
+        let frame = render(&review, cols as usize, rows as usize);
+        term.write_str(&format!("\x1b[H\x1b[2J{}", frame.join("\n")))?;
+        term.flush()?;
+
+        let page = (rows as isize - 4).max(1);
+        match term.read_key()? {
+            Key::ArrowUp | Key::Char('k') => review.move_by(-1),
+            Key::ArrowDown | Key::Char('j') => review.move_by(1),
+            Key::ArrowLeft | Key::Char('b') => review.scroll_by(-page),
+            Key::ArrowRight | Key::Char(' ') => review.scroll_by(page),
+            Key::Char('a') => review.decide(Decision::Accept),
+            Key::Char('r') => review.decide(Decision::Reject),
+            Key::Char('s') => review.skip(),
+            Key::Char('f') => review.accept_file(),
+            Key::Char('u') => review.undo(),
+            Key::Char('q') | Key::Enter => return Ok(Some(review.decisions)),
+            Key::Escape => return Ok(None),
+            _ => (),
+        }
+    }
+}
+
+/// Reviews `items` and applies the decisions, printing what was done. Returns whether they were
+/// applied, rather than the user quitting without changes.
+pub fn review_and_apply(items: &[ReviewItem]) -> SnapResult<bool> {
+    let decisions = match review(items)? {
+        Some(decisions) => decisions,
+        None => return Ok(false),
+    };
+
+    let (accepted, rejected) = apply(items, &decisions)?;
+    println!("\nAccepted {} and rejected {} snapshot(s), {} left undecided.",
+             accepted,
+             rejected,
+             items.len() - accepted - rejected);
+    Ok(true)
+}
+
+/// Switches to the terminal's alternate screen, switching back when dropped so errors and
+/// Ctrl-C leave the terminal as it was.
+struct AlternateScreen<'a>(&'a Term);
+
+impl<'a> AlternateScreen<'a> {
+    fn enter(term: &'a Term) -> SnapResult<Self> {
+        term.write_str("\x1b[?1049h\x1b[?25l")?;
+        Ok(AlternateScreen(term))
+    }
+}
+
+impl<'a> Drop for AlternateScreen<'a> {
+    fn drop(&mut self) {
+        let _ = self.0.write_str("\x1b[?25h\x1b[?1049l");
+        let _ = self.0.flush();
+    }
+}
+
+/// Draws the item list on the left and the selected item's diff on the right.
+fn render(review: &Review, width: usize, height: usize) -> Vec<String> {
+    let list_width = (width / 3).clamp(20, 50).min(width);
+    let diff_width = width.saturating_sub(list_width + 3);
+    let body_height = height.saturating_sub(3);
+
+    let selected = &review.items[review.selected];
+    let header = [
+        (Style::new().bold(), selected.key.clone()),
+        (Style::new().dim(), format!("{} in {}", selected.status, selected.location)),
+        (Style::new(), String::new()),
+    ];
+    let body = review.diff();
+    let scroll = review.scroll.min((header.len() + body.len()).saturating_sub(1));
+
+    // keep the selection in view
+    let list_start = (review.selected + 1).saturating_sub(body_height);
+
+    let mut lines = vec![Style::new().bold().apply_to(fit(
+        &format!("Snapshot review: {} of {} decided", review.decided(), review.items.len()),
+        width,
+    )).to_string()];
+
+    for row in 0..body_height {
+        let left = match review.items.get(list_start + row) {
+            Some(item) => {
+                let idx = list_start + row;
+                let mark = match review.decisions[idx] {
+                    Some(Decision::Accept) => "[a]",
+                    Some(Decision::Reject) => "[r]",
+                    None => "[ ]",
+                };
+                let text = fit(&format!("{} {}", mark, item.key), list_width);
+                if idx == review.selected {
+                    Style::new().reverse().apply_to(text).to_string()
+                } else {
+                    text
+                }
+            }
+            None => fit("", list_width),
+        };
+
+        let line = scroll + row;
+        let right = match header.get(line).or_else(|| body.get(line - header.len())) {
+            Some((style, text)) => style.apply_to(fit(text, diff_width)).to_string(),
+            None => String::new(),
+        };
+
+        lines.push(format!("{} | {}", left, right));
+    }
+
+    lines.push(String::new());
+    lines.push(Style::new().dim().apply_to(fit(
+        "up/down: select  left/right: scroll  a: accept  r: reject  s: skip  \
+         f: accept file  u: undo  q: apply and quit  esc: quit without changes",
+        width,
+    )).to_string());
+
+    lines
+}
+
+/// The selected item's recorded and new values as a unified diff.
+fn diff_lines(item: &ReviewItem) -> SnapResult<Vec<DiffLine>> {
+    let old = match item.old {
+        Some(ref old) => render_value(old)?,
+        None => String::new(),
+    };
+    let new = render_value(&item.new)?;
+
+    let removed = Style::new().red();
+    let added = Style::new().green();
+    let mut lines = Vec::new();
+    for row in align_lines(&old, &new) {
+        match row {
+            Row::Same(line) => lines.push((Style::new(), format!("  {}", line))),
+            Row::Changed(old, new) => {
+                lines.push((removed.clone(), format!("- {}", old)));
+                lines.push((added.clone(), format!("+ {}", new)));
+            }
+            Row::Removed(old) => lines.push((removed.clone(), format!("- {}", old))),
+            Row::Added(new) => lines.push((added.clone(), format!("+ {}", new))),
+        }
+    }
+    Ok(lines)
+}
+
+/// Pads or cuts `text` to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text.chars().take(width).collect();
+    let len = fitted.chars().count();
+    fitted.push_str(&" ".repeat(width - len));
+    fitted
+}
+
+/// Writes accepted values to their `.snap` files, and clears decided values from pending files.
+/// Returns how many values were accepted and rejected.
+pub fn apply(items: &[ReviewItem], decisions: &[Option<Decision>]) -> SnapResult<(usize, usize)> {
+    let mut accepted: BTreeMap<&Path, Vec<&ReviewItem>> = BTreeMap::new();
+    let mut settled: BTreeMap<PathBuf, Vec<&ReviewItem>> = BTreeMap::new();
+    let (mut accept_count, mut reject_count) = (0, 0);
+
+    for (item, decision) in items.iter().zip(decisions) {
+        match decision {
+            Some(Decision::Accept) => {
+                accepted.entry(&item.snap_file).or_default().push(item);
+                accept_count += 1;
+            }
+            Some(Decision::Reject) => reject_count += 1,
+            None => continue,
+        }
+
+        if item.pending {
+            settled.entry(Layout::pending_path(&item.snap_file)).or_default().push(item);
+        }
+    }
+
+    for (snap_path, items) in accepted {
+        if let Some(dir) = snap_path.parent() {
+            fs::create_dir_all(dir).chain_err(|| format!("unable to create {:?}", dir))?;
+        }
+
+        update_locked(snap_path, items[0].format, |contents| {
+            for item in &items {
+                let (module_path, test_function) = match item.key.rfind("::") {
+                    Some(idx) => (&item.key[..idx], &item.key[idx + 2..]),
+                    None => ("", item.key.as_str()),
+                };
+                contents.insert(item.key.clone(),
+                                Snapshot::new(item.source_file.to_string_lossy().into_owned(),
+                                              module_path.to_owned(),
+                                              test_function.to_owned(),
+                                              item.new.clone()));
+            }
+        })?;
+    }
+
+    for (pending_path, items) in settled {
+        update_locked(&pending_path, items[0].format, |contents| {
+            for item in &items {
+                contents.remove(&item.key);
+            }
+        })?;
+    }
+
+    Ok((accept_count, reject_count))
+}
+
+/// Updates a snapshot file under the same exclusive lock the library takes when tests write to it,
+/// re-reading it once locked so values written since the review started aren't lost. Removes the
+/// file if nothing is left in it.
+fn update_locked<F>(path: &Path, format: Format, update: F) -> SnapResult<()>
+    where F: FnOnce(&mut SnapFileContents)
+{
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(path)
+        .chain_err(|| format!("unable to open {:?}", path))?;
+    file.lock_exclusive().chain_err(|| format!("unable to lock {:?}", path))?;
+
+    let mut serialized = String::new();
+    file.read_to_string(&mut serialized).chain_err(|| format!("unable to read {:?}", path))?;
+    let mut contents = if serialized.is_empty() {
+        SnapFileContents::new()
+    } else {
+        serde_json::from_str(&serialized)
+            .chain_err(|| format!("unable to parse snapshot file {:?}", path))?
+    };
+
+    update(&mut contents);
+
+    if contents.is_empty() {
+        fs::remove_file(path).chain_err(|| format!("unable to remove {:?}", path))?;
+    } else {
+        let serialized = match format {
+            Format::Pretty => serde_json::to_string_pretty(&contents),
+            Format::Compact => serde_json::to_string(&contents),
+        }.chain_err(|| format!("unable to serialize {:?}", path))?;
+
+        file.seek(SeekFrom::Start(0))
+            .and_then(|_| file.set_len(0))
+            .and_then(|_| file.write_all(serialized.as_bytes()))
+            .chain_err(|| format!("unable to write {:?}", path))?;
+    }
+
+    let _ = file.unlock();
+    Ok(())
+}
+
+#[cfg(test)]
+mod review_tests {
+    use super::{apply, fit, Decision, Review, ReviewItem};
+    use crate::snap_files::SnapFile;
+    use serde_json::Value;
+    use snapshot::config::Format;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn item(key: &str, snap_file: &str) -> ReviewItem {
+        ReviewItem {
+            key: key.to_owned(),
+            snap_file: PathBuf::from(snap_file),
+            location: snap_file.to_owned(),
+            source_file: PathBuf::from("tests/simple.rs"),
+            status: "mismatched",
+            old: Some(Value::from(1)),
+            new: Value::from(2),
+            pending: false,
+            format: Format::Pretty,
+        }
+    }
+
+    #[test]
+    fn it_should_record_and_undo_decisions() {
+        let items = vec![item("simple::a", "a.snap"), item("simple::b", "b.snap"),
+                         item("simple::c", "a.snap")];
+        let mut review = Review::new(&items);
+
+        review.decide(Decision::Reject);
+        assert_eq!(review.selected, 1);
+        review.accept_file();
+        assert_eq!(review.decisions, vec![Some(Decision::Reject), Some(Decision::Accept), None]);
+        assert_eq!(review.selected, 2);
+
+        review.move_by(-2);
+        review.accept_file();
+        assert_eq!(review.decisions,
+                   vec![Some(Decision::Accept), Some(Decision::Accept), Some(Decision::Accept)]);
+
+        review.undo();
+        assert_eq!(review.decisions, vec![Some(Decision::Reject), Some(Decision::Accept), None]);
+        assert_eq!(review.selected, 0);
+
+        review.undo();
+        review.undo();
+        review.undo();
+        assert_eq!(review.decisions, vec![None, None, None]);
+        assert_eq!(review.decided(), 0);
+    }
+
+    #[test]
+    fn it_should_load_each_diff_once() {
+        let items = vec![item("simple::a", "a.snap"), item("simple::b", "b.snap")];
+        let mut review = Review::new(&items);
+        assert!(review.diff().is_empty());
+
+        review.load_diff().unwrap();
+        let lines: Vec<String> = review.diff().iter().map(|(_, text)| text.clone()).collect();
+        assert_eq!(lines, vec!["- 1", "+ 2"]);
+
+        review.move_by(1);
+        assert!(review.diff().is_empty());
+        review.move_by(-1);
+        assert_eq!(review.diff().len(), 2);
+    }
+
+    #[test]
+    fn it_should_fit_text_to_a_width() {
+        assert_eq!(fit("simple", 3), "sim");
+        assert_eq!(fit("ab", 4), "ab  ");
+    }
+
+    #[test]
+    fn it_should_apply_decisions() {
+        let dir = ::std::env::temp_dir().join(format!("cargo-snapshot-review-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let snap_path = dir.join("tests/__snapshots__/simple.rs.snap");
+        let snap = snap_path.to_str().unwrap();
+
+        fs::create_dir_all(snap_path.parent().unwrap()).unwrap();
+        fs::write(format!("{}.pending", snap),
+                  r#"{"simple::test::pending": {"file": ["tests", "simple.rs"],
+                      "module_path": "simple::test", "test_function": "pending",
+                      "recorded_value": 3}}"#).unwrap();
+
+        let mut accepted = item("simple::test::accepted", snap);
+        accepted.status = "missing";
+        let mut pending = item("simple::test::pending", snap);
+        pending.pending = true;
+        let items = vec![accepted, pending, item("simple::test::skipped", snap)];
+
+        let counts = apply(&items, &[Some(Decision::Accept), Some(Decision::Reject), None]).unwrap();
+        assert_eq!(counts, (1, 1));
+
+        let written = SnapFile::read(&snap_path).unwrap().contents;
+        assert_eq!(written.keys().collect::<Vec<_>>(), vec!["simple::test::accepted"]);
+        let snapshot = &written["simple::test::accepted"];
+        assert_eq!(snapshot.module_path, "simple::test");
+        assert_eq!(snapshot.test_function, "accepted");
+        assert_eq!(snapshot.file, vec!["tests", "simple.rs"]);
+        assert_eq!(snapshot.recorded_value, Value::from(2));
+        assert!(!PathBuf::from(format!("{}.pending", snap)).exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn it_should_keep_values_written_during_the_review() {
+        let dir = ::std::env::temp_dir().join(format!("cargo-snapshot-merge-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let snap_path = dir.join("simple.rs.snap");
+        let snap = snap_path.to_str().unwrap();
+        let items = vec![item("simple::test::accepted", snap)];
+
+        // a test recording its snapshot while the review is open
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&snap_path,
+                  r#"{"simple::test::recorded": {"file": ["tests", "simple.rs"],
+                      "module_path": "simple::test", "test_function": "recorded",
+                      "recorded_value": 3}}"#).unwrap();
+
+        apply(&items, &[Some(Decision::Accept)]).unwrap();
+
+        let written = SnapFile::read(&snap_path).unwrap().contents;
+        assert_eq!(written.keys().collect::<Vec<_>>(),
+                   vec!["simple::test::accepted", "simple::test::recorded"]);
+        assert_eq!(written["simple::test::recorded"].recorded_value, Value::from(3));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
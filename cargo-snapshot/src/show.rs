@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use snapshot::Snapshot;
+
+use crate::snap_files::{SnapFile, SnapFiles};
+use crate::workspace::Package;
+use crate::{relative_to, ResultExt, SnapResult};
+
+/// Prints the recorded value of the snapshot matching `query`, failing unless exactly one does.
+pub fn show(packages: &[&Package], query: &str, cwd: &Path) -> SnapResult<()> {
+    let mut snap_files = Vec::new();
+    for package in packages {
+        let found = SnapFiles::find(package.manifest_dir(), &package.config()?.layout())?;
+        snap_files.extend(found.snapshots);
+    }
+
+    let candidates: Vec<(&SnapFile, &str)> = snap_files
+        .iter()
+        .flat_map(|snap_file| snap_file.contents.keys().map(move |key| (snap_file, key.as_str())))
+        .collect();
+
+    let matches = find_matches(query, candidates.iter().map(|&(_, key)| key));
+    let (snap_file, key) = match matches.len() {
+        0 => bail!("no snapshot matches `{}`", query),
+        1 => candidates[matches[0]],
+        _ => {
+            println!("`{}` matches more than one snapshot:", query);
+            for idx in matches {
+                let (snap_file, key) = candidates[idx];
+                println!("    {} in {}", key, relative_to(cwd, &snap_file.path));
+            }
+            bail!("use a longer or exact key to pick one snapshot");
+        }
+    };
+
+    let snapshot = &snap_file.contents[key];
+    println!("{}", key);
+    print_metadata(snapshot, &snap_file.path, cwd);
+    println!();
+    println!("{}", render_value(&snapshot.recorded_value)?);
+
+    Ok(())
+}
+
+fn print_metadata(snapshot: &Snapshot<Value>, snap_path: &Path, cwd: &Path) {
+    let source_file: PathBuf = snapshot.file.iter().collect();
+    println!("    module: {}", snapshot.module_path);
+    println!("    test: {}", snapshot.test_function);
+    println!("    source file: {}", source_file.display());
+    println!("    snapshot file: {}", relative_to(cwd, snap_path));
+}
+
+/// Strings are printed verbatim so multiline text reads naturally, everything else as pretty JSON.
+pub fn render_value(value: &Value) -> SnapResult<String> {
+    match *value {
+        Value::String(ref text) => Ok(text.clone()),
+        _ => serde_json::to_string_pretty(value).chain_err(|| "unable to render snapshot value"),
+    }
+}
+
+/// Indices of the keys matching `query`: an exact match if there is one, otherwise keys
+/// containing it, otherwise keys containing its characters in order.
+fn find_matches<'a, I>(query: &str, keys: I) -> Vec<usize>
+where
+    I: Iterator<Item = &'a str> + Clone,
+{
+    let matching = |is_match: &dyn Fn(&str) -> bool| -> Vec<usize> {
+        keys.clone()
+            .enumerate()
+            .filter(|&(_, key)| is_match(key))
+            .map(|(idx, _)| idx)
+            .collect()
+    };
+
+    let exact = matching(&|key| key == query);
+    if !exact.is_empty() {
+        return exact;
+    }
+
+    let query = query.to_lowercase();
+    let containing = matching(&|key| key.to_lowercase().contains(&query));
+    if !containing.is_empty() {
+        return containing;
+    }
+
+    matching(&|key| {
+        let mut key_chars = key.chars().flat_map(char::to_lowercase);
+        query.chars().all(|q| key_chars.any(|k| k == q))
+    })
+}
+
+#[cfg(test)]
+mod show_tests {
+    use super::{find_matches, render_value};
+    use serde_json::Value;
+
+    static KEYS: &[&str] = &[
+        "simple::test::simple_snapshot",
+        "simple::test::sub_test::simple_snapshot",
+        "simple::test::compound_snapshot",
+    ];
+
+    fn matches(query: &str) -> Vec<usize> {
+        find_matches(query, KEYS.iter().cloned())
+    }
+
+    #[test]
+    fn it_should_prefer_exact_matches() {
+        assert_eq!(matches("simple::test::simple_snapshot"), vec![0]);
+        assert_eq!(matches("simple_snapshot"), vec![0, 1]);
+        assert_eq!(matches("Compound"), vec![2]);
+        assert_eq!(matches("subsimple"), vec![1]);
+        assert!(matches("nothing").is_empty());
+    }
+
+    #[test]
+    fn it_should_print_strings_verbatim() {
+        assert_eq!(render_value(&Value::from("a\nb")).unwrap(), "a\nb");
+        assert_eq!(render_value(&Value::from(vec![1])).unwrap(), "[\n  1\n]");
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use snapshot::config::Layout;
+use snapshot::SnapFileContents;
+use walkdir::{DirEntry, WalkDir, WalkDirIterator};
+
+use crate::{ResultExt, SnapResult};
+
+/// A parsed snapshot file, or a pending side file waiting for review.
+pub struct SnapFile {
+    pub path: PathBuf,
+    pub contents: SnapFileContents,
+}
+
+/// Every snapshot and pending file in a package.
+pub struct SnapFiles {
+    pub snapshots: Vec<SnapFile>,
+    pub pending: Vec<SnapFile>,
+}
+
+impl SnapFiles {
+    pub fn find(root: &Path, layout: &Layout) -> SnapResult<Self> {
+        let mut found = SnapFiles {
+            snapshots: Vec::new(),
+            pending: Vec::new(),
+        };
+
+        for walk_result in WalkDir::new(root).into_iter().filter_entry(is_searched) {
+            let entry = walk_result.chain_err(|| "unable to traverse project directory")?;
+
+            if layout.is_snap_file(entry.path()) {
+                found.snapshots.push(SnapFile::read(entry.path())?);
+            } else if layout.is_pending_file(entry.path()) {
+                found.pending.push(SnapFile::read(entry.path())?);
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl SnapFile {
+    pub fn read(path: &Path) -> SnapResult<Self> {
+        let rdr = BufReader::new(
+            File::open(path).chain_err(|| format!("unable to open snapshot file {:?}", path))?,
+        );
+        let contents = serde_json::from_reader(rdr)
+            .chain_err(|| format!("unable to parse snapshot file {:?}", path))?;
+
+        Ok(SnapFile {
+            path: path.to_owned(),
+            contents,
+        })
+    }
+}
+
+/// Skips build output, hidden directories like `.git`, and nested packages which have their own
+/// snapshots.
+pub fn is_searched(entry: &DirEntry) -> bool {
+    if entry.depth() == 0 {
+        return true;
+    }
+
+    let name = entry.file_name().to_string_lossy();
+    let is_package = entry.file_type().is_dir() && entry.path().join("Cargo.toml").exists();
+    !(name == "target" || name.starts_with('.') || is_package)
+}
+
+/// A snapshot file or entry whose test doesn't seem to exist anymore.
+pub struct Obsolete {
+    pub snap_file: PathBuf,
+    pub key: Option<String>,
+    pub reason: String,
+}
+
+/// Finds snapshots whose source file is gone, or whose source no longer defines the test.
+pub fn find_obsolete(snap_file: &SnapFile, layout: &Layout) -> Vec<Obsolete> {
+    let mut obsolete = Vec::new();
+
+    for (key, snapshot) in &snap_file.contents {
+        let manifest_dir = match layout.manifest_dir_of(&snap_file.path, &snapshot.file) {
+            Some(dir) => dir,
+            None => continue,
+        };
+
+        let source_file: PathBuf = snapshot.file.iter().collect();
+        match fs::read_to_string(manifest_dir.join(&source_file)) {
+            Ok(source) => {
+                if !defines_fn(&source, &snapshot.test_function) {
+                    obsolete.push(Obsolete {
+                        snap_file: snap_file.path.clone(),
+                        key: Some(key.clone()),
+                        reason: format!(
+                            "no `fn {}` in {}",
+                            snapshot.test_function,
+                            source_file.display()
+                        ),
+                    });
+                }
+            }
+            Err(_) => {
+                // every entry in the file shares the missing source, so report it once
+                obsolete.push(Obsolete {
+                    snap_file: snap_file.path.clone(),
+                    key: None,
+                    reason: format!("source file {} no longer exists", source_file.display()),
+                });
+                break;
+            }
+        }
+    }
+
+    obsolete
+}
+
+/// Whether `fn name` appears anywhere in `source`. This is a text search rather than a parse, so
+/// a match in a comment, a string or another module counts too.
+fn defines_fn(source: &str, name: &str) -> bool {
+    let needle = format!("fn {}", name);
+    source.match_indices(&needle).any(|(idx, _)| {
+        let after = source[idx + needle.len()..].chars().next();
+        let before = source[..idx].chars().next_back();
+
+        !after.is_some_and(|c| c.is_alphanumeric() || c == '_')
+            && !before.is_some_and(|c| c.is_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod snap_file_tests {
+    use super::defines_fn;
+
+    #[test]
+    fn it_should_find_fn_definitions() {
+        let source = "#[snapshot]\nfn simple_snapshot() -> i32 {\n    1\n}\n";
+
+        assert!(defines_fn(source, "simple_snapshot"));
+        assert!(!defines_fn(source, "simple"));
+        assert!(!defines_fn(source, "snapshot"));
+    }
+}
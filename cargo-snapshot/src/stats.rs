@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use snapshot::events;
+
+use crate::snap_files::{find_obsolete, Obsolete, SnapFiles};
+use crate::workspace::{Package, Workspace};
+use crate::{relative_to, ResultExt, SnapResult};
+
+/// What `cargo snapshot stats` reports about the snapshots of some packages.
+pub struct Stats {
+    pub files: Vec<FileStats>,
+    /// The biggest recorded values by their length as compact JSON, largest first.
+    pub largest: Vec<(String, usize)>,
+    /// Groups of snapshots which recorded the same value.
+    pub duplicates: Vec<Vec<String>>,
+    pub obsolete: Vec<Obsolete>,
+    /// Snapshots without an event in the last run's log, or `None` if nothing was logged.
+    pub unexercised: Option<Vec<String>>,
+}
+
+pub struct FileStats {
+    pub path: PathBuf,
+    pub package: String,
+    pub snapshots: usize,
+    pub bytes: u64,
+}
+
+impl Stats {
+    pub fn collect(workspace: &Workspace, packages: &[&Package], top: usize) -> SnapResult<Self> {
+        let logged = events::read_log(&workspace.event_log())?;
+        let exercised: BTreeSet<(&str, &Path)> = logged
+            .iter()
+            .map(|event| (event.key.as_str(), event.manifest_dir.as_path()))
+            .collect();
+
+        let mut files = Vec::new();
+        let mut obsolete = Vec::new();
+        let mut values = Vec::new();
+        let mut unexercised = Vec::new();
+
+        for package in packages {
+            let layout = package.config()?.layout();
+            let found = SnapFiles::find(package.manifest_dir(), &layout)?;
+
+            for snap_file in found.snapshots {
+                let bytes = fs::metadata(&snap_file.path)
+                    .chain_err(|| format!("unable to read {:?}", snap_file.path))?
+                    .len();
+                files.push(FileStats {
+                    path: snap_file.path.clone(),
+                    package: package.name.clone(),
+                    snapshots: snap_file.contents.len(),
+                    bytes,
+                });
+
+                obsolete.extend(find_obsolete(&snap_file, &layout));
+
+                for (key, snapshot) in snap_file.contents {
+                    if !exercised.contains(&(key.as_str(), package.manifest_dir())) {
+                        unexercised.push(key.clone());
+                    }
+                    values.push((key, snapshot.recorded_value));
+                }
+            }
+        }
+
+        Ok(Stats {
+            files,
+            largest: largest(&values, top),
+            duplicates: duplicates(&values),
+            obsolete,
+            unexercised: if logged.is_empty() { None } else { Some(unexercised) },
+        })
+    }
+
+    pub fn print(&self, cwd: &Path) {
+        let mut packages: BTreeMap<&str, (usize, usize, u64)> = BTreeMap::new();
+        for file in &self.files {
+            let totals = packages.entry(&file.package).or_insert((0, 0, 0));
+            totals.0 += 1;
+            totals.1 += file.snapshots;
+            totals.2 += file.bytes;
+        }
+
+        println!("Snapshot files:");
+        for file in &self.files {
+            println!("    {}: {} snapshot(s), {} bytes",
+                     relative_to(cwd, &file.path),
+                     file.snapshots,
+                     file.bytes);
+        }
+
+        println!("\nPackages:");
+        for (package, (files, snapshots, bytes)) in &packages {
+            println!("    {}: {} snapshot(s) in {} file(s), {} bytes",
+                     package,
+                     snapshots,
+                     files,
+                     bytes);
+        }
+
+        println!("\nLargest snapshots:");
+        for (key, size) in &self.largest {
+            println!("    {}: {} bytes", key, size);
+        }
+
+        if !self.obsolete.is_empty() {
+            println!("\nSnapshots with no test:");
+            for obsolete in &self.obsolete {
+                let location = relative_to(cwd, &obsolete.snap_file);
+                match obsolete.key {
+                    Some(ref key) => println!("    {} in {}, {}", key, location, obsolete.reason),
+                    None => println!("    {}, {}", location, obsolete.reason),
+                }
+            }
+        }
+
+        if !self.duplicates.is_empty() {
+            println!("\nSnapshots recording the same value:");
+            for keys in &self.duplicates {
+                println!("    {}", keys.join(", "));
+            }
+        }
+
+        match self.unexercised {
+            Some(ref keys) if !keys.is_empty() => {
+                println!("\nSnapshots not exercised by the last run:");
+                for key in keys {
+                    println!("    {}", key);
+                }
+            }
+            Some(_) => (),
+            None => println!("\nNo test run was logged, run `cargo snapshot test` to find \
+                              snapshots which aren't exercised."),
+        }
+    }
+}
+
+/// The `top` biggest values by their length as compact JSON.
+fn largest(values: &[(String, Value)], top: usize) -> Vec<(String, usize)> {
+    let mut sizes: Vec<(String, usize)> = values
+        .iter()
+        .map(|(key, value)| (key.clone(), value.to_string().len()))
+        .collect();
+    sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    sizes.truncate(top);
+    sizes
+}
+
+/// Keys of snapshots which recorded the same value, biggest values first and then by key.
+fn duplicates(values: &[(String, Value)]) -> Vec<Vec<String>> {
+    let mut by_value: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (key, value) in values {
+        by_value.entry(value.to_string()).or_default().push(key.clone());
+    }
+
+    let mut groups: Vec<(String, Vec<String>)> = by_value
+        .into_iter()
+        .filter(|(_, keys)| keys.len() > 1)
+        .collect();
+    groups.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.1.cmp(&b.1)));
+    groups.into_iter().map(|(_, keys)| keys).collect()
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::{duplicates, largest};
+    use serde_json::Value;
+
+    fn values() -> Vec<(String, Value)> {
+        vec![("a".to_owned(), Value::from("long string")),
+             ("b".to_owned(), Value::from(1)),
+             ("c".to_owned(), Value::from("long string")),
+             ("d".to_owned(), Value::from(vec![1, 2])),
+             ("e".to_owned(), Value::from(1))]
+    }
+
+    #[test]
+    fn it_should_find_the_largest_snapshots() {
+        assert_eq!(largest(&values(), 3),
+                   vec![("a".to_owned(), 13), ("c".to_owned(), 13), ("d".to_owned(), 5)]);
+    }
+
+    #[test]
+    fn it_should_group_duplicate_values() {
+        assert_eq!(duplicates(&values()), vec![vec!["a", "c"], vec!["b", "e"]]);
+    }
+
+    #[test]
+    fn it_should_order_equal_sized_duplicates_by_key() {
+        let values: Vec<(String, Value)> = vec![("x".to_owned(), Value::from(1)),
+                                                ("p".to_owned(), Value::from(2)),
+                                                ("y".to_owned(), Value::from(1)),
+                                                ("q".to_owned(), Value::from(2))];
+        assert_eq!(duplicates(&values), vec![vec!["p", "q"], vec!["x", "y"]]);
+    }
+}
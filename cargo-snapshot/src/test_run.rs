@@ -0,0 +1,601 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use duct::cmd;
+use nom::IResult;
+use snapshot::events::{self, Event, SNAPSHOT_EVENT_LOG_VAR};
+
+use crate::cargo_args::CargoArgs;
+use crate::parse_json::{JsonStream, JsonSuite};
+use crate::parse_tests::{cargo_test_result_parser, Suite};
+use crate::snap_files::SnapFiles;
+use crate::workspace::{Package, Workspace};
+use crate::{ResultExt, SnapResult};
+
+/// What happened to a single snapshot test during a run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Matched,
+    Mismatched,
+    Missing,
+    Written,
+    Failed,
+}
+
+impl Outcome {
+    pub fn all() -> [Outcome; 5] {
+        [Outcome::Matched, Outcome::Mismatched, Outcome::Missing, Outcome::Written, Outcome::Failed]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Outcome::Matched => "matched",
+            Outcome::Mismatched => "mismatched",
+            Outcome::Missing => "missing",
+            Outcome::Written => "written",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+/// How the results of a test run were read.
+pub enum Output {
+    /// libtest's JSON events, one suite per test executable.
+    Json(Vec<JsonSuite>),
+    /// The human-readable output of `cargo test`.
+    Text(String),
+}
+
+/// The results of running the tests and whether they succeeded.
+pub struct TestRun {
+    pub success: bool,
+    pub output: Output,
+    /// What each snapshot assertion reported, in the order they ran.
+    pub events: Vec<Event>,
+}
+
+impl TestRun {
+    /// The suites that reported results, which are none if the tests failed to build.
+    pub fn suites(&self) -> Vec<Suite<'_, '_, '_, '_, '_>> {
+        match self.output {
+            Output::Json(ref suites) => suites.iter().map(JsonSuite::suite).collect(),
+            Output::Text(ref output) => parse_output(output),
+        }
+    }
+}
+
+/// Runs the tests with the user's arguments, where anything after `--` goes to the test
+/// executables, and `env` is set for the tests. Their output is printed as it arrives if `echo`
+/// is set.
+///
+/// `--no-fail-fast` is added so that one failing test binary doesn't hide the results of the rest.
+/// Results are read from libtest's JSON output on nightly toolchains, which are the only ones to
+/// accept it, and from the human-readable output of `cargo test` everywhere else.
+pub fn run_cargo_test(
+    workspace: &Workspace,
+    args: &[String],
+    env: &[(&str, &str)],
+    echo: bool,
+) -> SnapResult<TestRun> {
+    let (cargo_args, test_args) = match args.iter().position(|a| a == "--") {
+        Some(idx) => (&args[..idx], &args[idx + 1..]),
+        None => (args, &[][..]),
+    };
+    let json = supports_json_output();
+
+    let mut all_args = vec!["test".to_owned()];
+    all_args.extend(cargo_args.iter().cloned());
+    if !cargo_args.iter().any(|a| a == "--no-fail-fast") {
+        all_args.push("--no-fail-fast".to_owned());
+    }
+    if json || !test_args.is_empty() {
+        all_args.push("--".to_owned());
+    }
+    if json {
+        all_args.extend(["-Z", "unstable-options", "--format", "json"].iter().map(|a| a.to_string()));
+    }
+    all_args.extend(test_args.iter().cloned());
+
+    let log = workspace.event_log();
+    start_event_log(&log)?;
+
+    let mut stream = JsonStream::default();
+    let mut shown = String::new();
+    let success = stream_cargo(&all_args, env, &log, |line| {
+        let text = if json { stream.push(line) } else { format!("{}\n", line) };
+        if echo {
+            print!("{}", text);
+        }
+        shown.push_str(&text);
+    })?;
+
+    let run = TestRun {
+        success,
+        output: if json { Output::Json(stream.finish()) } else { Output::Text(shown.clone()) },
+        events: events::read_log(&log)?,
+    };
+
+    // show compile errors, crashed test executables and the like, which can't be reported any
+    // other way
+    let failed_tests = run
+        .suites()
+        .iter()
+        .any(|suite| suite.tests.iter().any(|test| test.status == "fail"));
+    if !echo && !success && !failed_tests {
+        print!("{}", shown);
+    }
+    Ok(run)
+}
+
+/// Whether libtest accepts `--format json`, which is unstable, so only nightly toolchains do
+/// unless the user opted into unstable features with `RUSTC_BOOTSTRAP` themselves.
+fn supports_json_output() -> bool {
+    if env::var_os("RUSTC_BOOTSTRAP").is_some() {
+        return true;
+    }
+
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    match cmd(rustc, ["-V"]).stderr_null().unchecked().read() {
+        Ok(version) => version.contains("-nightly") || version.contains("-dev"),
+        Err(_) => false,
+    }
+}
+
+/// Creates an empty event log, clearing out the last run's events.
+fn start_event_log(log: &Path) -> SnapResult<()> {
+    if let Some(dir) = log.parent() {
+        fs::create_dir_all(dir).chain_err(|| format!("unable to create {:?}", dir))?;
+    }
+    fs::write(log, "").chain_err(|| format!("unable to create event log {:?}", log))?;
+    Ok(())
+}
+
+/// Runs cargo, handing each line of its output to `on_line` as soon as it's printed, and returns
+/// whether it succeeded.
+fn stream_cargo<F>(args: &[String], env: &[(&str, &str)], log: &Path, mut on_line: F) -> SnapResult<bool>
+where
+    F: FnMut(&str),
+{
+    let (reader, writer) = os_pipe::pipe().chain_err(|| "unable to create a pipe for cargo")?;
+
+    let mut command = cmd("cargo", args);
+    for (name, value) in env {
+        command = command.env(name, value);
+    }
+
+    // the expression holding the pipe's writer is dropped once cargo starts, so reading ends
+    // when cargo and the tests exit
+    let handle = command
+        .env(SNAPSHOT_EVENT_LOG_VAR, log)
+        .stderr_to_stdout()
+        .stdout_handle(writer)
+        .unchecked()
+        .start()
+        .chain_err(|| "unable to execute cargo")?;
+
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).chain_err(|| "unable to read cargo's output")? == 0 {
+            break;
+        }
+        let text = String::from_utf8_lossy(&line);
+        on_line(text.trim_end_matches(&['\n', '\r'][..]));
+    }
+
+    let output = handle.wait().chain_err(|| "unable to execute cargo")?;
+    Ok(output.status.success())
+}
+
+/// The key of every recorded snapshot in `packages`.
+pub fn recorded_keys(packages: &[&Package]) -> SnapResult<BTreeSet<String>> {
+    let mut keys = BTreeSet::new();
+
+    for package in packages {
+        let found = SnapFiles::find(package.manifest_dir(), &package.config()?.layout())?;
+        for snap_file in found.snapshots {
+            keys.extend(snap_file.contents.into_keys());
+        }
+    }
+
+    Ok(keys)
+}
+
+/// The last event of every snapshot that mismatched or was missing in a run.
+pub fn failed_snapshots(events: Vec<Event>) -> Vec<Event> {
+    let mut last_events = BTreeMap::new();
+    for event in events {
+        last_events.insert((event.key.clone(), event.manifest_dir.clone()), event);
+    }
+
+    last_events
+        .into_values()
+        .filter(|event| matches!(event.outcome, events::Outcome::Mismatched | events::Outcome::Missing))
+        .collect()
+}
+
+/// Parses the output of a test run into its suites. There are none if the tests failed to
+/// build, and a test executable that crashed has the results it printed before it stopped.
+pub fn parse_output(output: &str) -> Vec<Suite<'_, '_, '_, '_, '_>> {
+    match cargo_test_result_parser(output.as_bytes()) {
+        IResult::Done(_, suites) => suites,
+        _ => Vec::new(),
+    }
+}
+
+/// Works out what happened to each snapshot, using the last event the library logged for it.
+///
+/// Tests with a recorded snapshot that failed before reaching their assertion are reported as
+/// failed.
+pub fn classify(
+    suites: &[Suite],
+    events: &[Event],
+    recorded: &BTreeSet<String>,
+) -> Vec<(String, Outcome)> {
+    // keys are only unique within a crate, so tell apart the same key in different crates
+    let mut last_events = BTreeMap::new();
+    for event in events {
+        last_events.insert((event.key.as_str(), event.manifest_dir.as_path()), event);
+    }
+    let logged: BTreeSet<_> = last_events.keys().map(|(key, _)| *key).collect();
+
+    let mut results: Vec<_> = last_events
+        .values()
+        .map(|event| {
+            let outcome = match event.outcome {
+                events::Outcome::Matched => Outcome::Matched,
+                events::Outcome::Mismatched => Outcome::Mismatched,
+                events::Outcome::Missing => Outcome::Missing,
+                // rewriting a snapshot with the same value doesn't change anything
+                events::Outcome::Written if event.old_hash == event.new_hash => Outcome::Matched,
+                events::Outcome::Written => Outcome::Written,
+            };
+            (event.key.clone(), outcome)
+        })
+        .collect();
+
+    for suite in suites {
+        let crate_name = match suite_crate_name(suite.name) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        for test in suite.tests.iter().filter(|test| test.status == "fail") {
+            let key = format!("{}::{}", crate_name, test.name);
+            if recorded.contains(&key) && !logged.contains(key.as_str()) {
+                results.push((key, Outcome::Failed));
+            }
+        }
+    }
+
+    results
+}
+
+/// Prints how many snapshots ended up with each outcome, then every one that didn't match.
+pub fn print_summary(results: &[(String, Outcome)]) {
+    println!("\nSnapshot summary:");
+    for outcome in &Outcome::all() {
+        let count = results.iter().filter(|(_, o)| o == outcome).count();
+        println!("  {:<12}{:>5}", outcome.label(), count);
+    }
+
+    let mut unmatched: Vec<_> = results
+        .iter()
+        .filter(|(_, outcome)| *outcome != Outcome::Matched)
+        .collect();
+    unmatched.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+
+    if !unmatched.is_empty() {
+        println!();
+    }
+    for (key, outcome) in unmatched {
+        println!("  {}: {}", outcome.label(), key);
+    }
+}
+
+/// A test that failed for some reason other than its snapshot, like a panic in the test body.
+#[derive(Debug, PartialEq)]
+pub struct UnrelatedFailure<'a> {
+    pub key: String,
+    /// What the test printed, found by the failure parser.
+    pub output: Option<&'a str>,
+}
+
+/// Failed tests which didn't report a mismatched or missing snapshot.
+pub fn unrelated_failures<'a>(
+    suites: &[Suite<'a, 'a, 'a, 'a, 'a>],
+    events: &[Event],
+) -> Vec<UnrelatedFailure<'a>> {
+    let snapshot_failures: BTreeSet<&str> = events
+        .iter()
+        .filter(|event| matches!(event.outcome, events::Outcome::Mismatched | events::Outcome::Missing))
+        .map(|event| event.key.as_str())
+        .collect();
+
+    let mut failures = Vec::new();
+    for suite in suites {
+        let crate_name = suite_crate_name(suite.name).unwrap_or(suite.name);
+
+        for test in suite.tests.iter().filter(|test| test.status == "fail") {
+            let key = format!("{}::{}", crate_name, test.name);
+            if !snapshot_failures.contains(key.as_str()) {
+                failures.push(UnrelatedFailure {
+                    key,
+                    output: test.error,
+                });
+            }
+        }
+    }
+
+    failures
+}
+
+pub fn print_unrelated_failures(failures: &[UnrelatedFailure]) {
+    if failures.is_empty() {
+        return;
+    }
+
+    println!("\n{} test(s) failed for reasons other than their snapshots:", failures.len());
+    for failure in failures {
+        println!("\n---- {} ----", failure.key);
+        println!("{}", failure.output.unwrap_or("(no output was captured)"));
+    }
+}
+
+/// Tests to run from a single test target, matched exactly by their path within it.
+#[derive(Debug, PartialEq)]
+pub struct TestSelection {
+    /// Selects the package and target, like `-p app --test simple`. Only selects the target if
+    /// the package couldn't be found, and is empty if neither could be worked out.
+    pub target_args: Vec<String>,
+    pub names: Vec<String>,
+}
+
+impl TestSelection {
+    /// Arguments for `run_cargo_test` that run just these tests, along with the user's own.
+    pub fn cargo_args(&self, cargo_args: &CargoArgs) -> Vec<String> {
+        let mut test_args = vec!["--exact".to_owned()];
+        test_args.extend(self.names.iter().cloned());
+        cargo_args.with(&self.target_args, &test_args)
+    }
+}
+
+/// Groups the tests behind each event by their target, so each target is only run once.
+pub fn select_tests(workspace: &Workspace, events: &[&Event]) -> Vec<TestSelection> {
+    let mut selections: Vec<TestSelection> = Vec::new();
+
+    for event in events {
+        // keys start with the crate name, which is also the name of the target
+        let mut key_parts = event.key.splitn(2, "::");
+        let (crate_name, name) = match (key_parts.next(), key_parts.next()) {
+            (Some(crate_name), Some(name)) => (crate_name, name),
+            _ => continue,
+        };
+
+        let target_args = match workspace.package_at(&event.manifest_dir) {
+            Some(package) => {
+                let mut args = vec!["-p".to_owned(), package.name.clone()];
+                match package.target_for_crate(crate_name) {
+                    Some(target) => args.extend(target.cargo_args()),
+                    None => args.extend(target_args(&event.source_file, crate_name)),
+                }
+                args
+            }
+            None => target_args(&event.source_file, crate_name),
+        };
+        match selections.iter_mut().find(|s| s.target_args == target_args) {
+            Some(selection) => selection.names.push(name.to_owned()),
+            None => selections.push(TestSelection {
+                target_args,
+                names: vec![name.to_owned()],
+            }),
+        }
+    }
+
+    selections
+}
+
+/// The cargo arguments selecting the target built from `source_file`, guessed from cargo's
+/// conventional target layout.
+fn target_args(source_file: &Path, crate_name: &str) -> Vec<String> {
+    let components: Vec<_> = source_file
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let component = |idx: usize| components.get(idx).map(String::as_str);
+
+    let kind = match (component(0), component(1)) {
+        (Some("tests"), _) => "--test",
+        (Some("examples"), _) => "--example",
+        (Some("benches"), _) => "--bench",
+        (Some("src"), Some("bin")) => "--bin",
+        (Some("src"), Some("main.rs")) => "--bin",
+        (Some("src"), _) => return vec!["--lib".to_owned()],
+        _ => return Vec::new(),
+    };
+
+    vec![kind.to_owned(), crate_name.to_owned()]
+}
+
+/// The crate name of a suite's test binary, e.g. `simple` for
+/// `tests/simple.rs (target/debug/deps/simple-f90ad51e6d4ff9c3)`.
+pub fn suite_crate_name(suite_name: &str) -> Option<&str> {
+    let binary = match (suite_name.rfind('('), suite_name.rfind(')')) {
+        (Some(open), Some(close)) if open < close => &suite_name[open + 1..close],
+        _ => suite_name,
+    };
+
+    let file_name = binary.rsplit(['/', '\\']).next()?;
+    let stem = file_name.trim_end_matches(".exe");
+    let hash_start = stem.rfind('-')?;
+    Some(&stem[..hash_start])
+}
+
+#[cfg(test)]
+mod test_run_tests {
+    use super::{
+        classify, select_tests, suite_crate_name, unrelated_failures, Outcome, TestSelection,
+        UnrelatedFailure,
+    };
+    use crate::cargo_args::CargoArgs;
+    use crate::workspace::Workspace;
+    use crate::parse_tests::{Suite, Test};
+    use crate::test_support::event;
+    use snapshot::events::{self, Event};
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+
+    #[test]
+    fn it_should_find_crate_names() {
+        assert_eq!(
+            suite_crate_name("tests/simple.rs (target/debug/deps/simple-f90ad51e6d4ff9c3)"),
+            Some("simple")
+        );
+        assert_eq!(
+            suite_crate_name("target/debug/deps/my_crate-2c25f3ea4c0e3d3b"),
+            Some("my_crate")
+        );
+        assert_eq!(suite_crate_name("snapshot"), None);
+    }
+
+    #[test]
+    fn it_should_find_unrelated_failures() {
+        let suites = vec![Suite {
+            name: "tests/simple.rs (target/debug/deps/simple-f90ad51e6d4ff9c3)",
+            state: "fail",
+            passed: 1,
+            failed: 2,
+            ignored: 0,
+            measured: 0,
+            total: 3,
+            tests: vec![
+                Test {
+                    name: "mismatched",
+                    status: "fail",
+                    error: Some("Test output doesn't match recorded snapshot!"),
+                },
+                Test {
+                    name: "panicked",
+                    status: "fail",
+                    error: Some("attempt to divide by zero"),
+                },
+                Test {
+                    name: "passed",
+                    status: "pass",
+                    error: None,
+                },
+            ],
+        }];
+        let events = vec![event("simple::mismatched", events::Outcome::Mismatched)];
+
+        assert_eq!(
+            unrelated_failures(&suites, &events),
+            vec![UnrelatedFailure {
+                key: "simple::panicked".to_owned(),
+                output: Some("attempt to divide by zero"),
+            }]
+        );
+    }
+
+    #[test]
+    fn it_should_select_tests_by_target() {
+        let event = |key: &str, source_file: &str| Event {
+            source_file: PathBuf::from(source_file),
+            ..event(key, events::Outcome::Mismatched)
+        };
+        let events = [
+            event("simple::test::simple_snapshot", "tests/simple.rs"),
+            event("second::test::simple_snapshot", "tests/second.rs"),
+            event("simple::test::sub_test::simple_snapshot", "tests/simple.rs"),
+            event("my_crate::parser::tests::parses", "src/parser.rs"),
+            event("tool::tests::runs", "src/bin/tool.rs"),
+        ];
+
+        let selection = |target_args: &[&str], names: &[&str]| TestSelection {
+            target_args: target_args.iter().map(|a| a.to_string()).collect(),
+            names: names.iter().map(|n| n.to_string()).collect(),
+        };
+
+        let workspace = Workspace {
+            root: PathBuf::from("/p"),
+            target_dir: PathBuf::from("/p/target"),
+            members: Vec::new(),
+            default_members: Vec::new(),
+        };
+        let selections = select_tests(&workspace, &events.iter().collect::<Vec<_>>());
+        assert_eq!(
+            selections,
+            vec![
+                selection(
+                    &["--test", "simple"],
+                    &["test::simple_snapshot", "test::sub_test::simple_snapshot"]
+                ),
+                selection(&["--test", "second"], &["test::simple_snapshot"]),
+                selection(&["--lib"], &["parser::tests::parses"]),
+                selection(&["--bin", "tool"], &["tests::runs"]),
+            ]
+        );
+        assert_eq!(
+            selections[1].cargo_args(&CargoArgs::default()),
+            vec!["--test", "second", "--", "--exact", "test::simple_snapshot"]
+        );
+    }
+
+    #[test]
+    fn it_should_classify_snapshot_tests() {
+        let test = |name, status| Test {
+            name,
+            status,
+            error: None,
+        };
+        let suites = vec![Suite {
+            name: "tests/simple.rs (target/debug/deps/simple-f90ad51e6d4ff9c3)",
+            state: "fail",
+            passed: 1,
+            failed: 3,
+            ignored: 0,
+            measured: 0,
+            total: 4,
+            tests: vec![
+                test("matched", "pass"),
+                test("mismatched", "fail"),
+                test("failed", "fail"),
+                test("unrelated", "fail"),
+            ],
+        }];
+
+        let event = |key: &str, outcome, old_hash: &str, new_hash: &str| Event {
+            old_hash: Some(old_hash.to_owned()).filter(|h| !h.is_empty()),
+            new_hash: Some(new_hash.to_owned()),
+            ..event(key, outcome)
+        };
+        let events = vec![
+            event("simple::matched", events::Outcome::Matched, "1", "1"),
+            event("simple::mismatched", events::Outcome::Mismatched, "1", "2"),
+            event("simple::missing", events::Outcome::Missing, "", "1"),
+            event("simple::rewritten", events::Outcome::Written, "1", "1"),
+            event("simple::written", events::Outcome::Written, "1", "2"),
+        ];
+
+        let recorded: BTreeSet<_> = ["simple::matched", "simple::mismatched", "simple::failed"]
+            .iter()
+            .map(|key| key.to_string())
+            .collect();
+
+        assert_eq!(
+            classify(&suites, &events, &recorded),
+            vec![
+                ("simple::matched".to_owned(), Outcome::Matched),
+                ("simple::mismatched".to_owned(), Outcome::Mismatched),
+                ("simple::missing".to_owned(), Outcome::Missing),
+                ("simple::rewritten".to_owned(), Outcome::Matched),
+                ("simple::written".to_owned(), Outcome::Written),
+                ("simple::failed".to_owned(), Outcome::Failed),
+            ]
+        );
+    }
+}
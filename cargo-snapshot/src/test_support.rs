@@ -0,0 +1,38 @@
+//! Fixtures shared by the unit tests.
+
+use std::path::PathBuf;
+
+use serde_json::Value;
+use snapshot::events::{Event, Outcome};
+use snapshot::{SnapFileContents, Snapshot};
+
+/// An event for `key` from `/ws/app/tests/simple.rs`, without hashes, a new value or a message.
+/// Tests needing more set those fields with struct update syntax.
+pub fn event(key: &str, outcome: Outcome) -> Event {
+    Event {
+        key: key.to_owned(),
+        manifest_dir: PathBuf::from("/ws/app"),
+        file: PathBuf::from("tests/__snapshots__/simple.rs.snap"),
+        source_file: PathBuf::from("tests/simple.rs"),
+        outcome,
+        old_hash: None,
+        new_hash: None,
+        new_value: None,
+        message: None,
+    }
+}
+
+/// The contents of `tests/__snapshots__/simple.rs.snap`, with a `simple::test::<function>` entry
+/// for each function and value.
+pub fn contents<V: Clone + Into<Value>>(entries: &[(&str, V)]) -> SnapFileContents {
+    entries
+        .iter()
+        .map(|(function, value)| {
+            (format!("simple::test::{}", function),
+             Snapshot::new("tests/simple.rs".to_owned(),
+                           "simple::test".to_owned(),
+                           function.to_string(),
+                           value.clone().into()))
+        })
+        .collect()
+}
@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use console::Term;
+use walkdir::{WalkDir, WalkDirIterator};
+
+use crate::cargo_args::CargoArgs;
+use crate::review::{review_and_apply, review_items};
+use crate::snap_files::is_searched;
+use crate::test_run::{classify, failed_snapshots, print_summary, print_unrelated_failures,
+                      recorded_keys, run_cargo_test, unrelated_failures};
+use crate::workspace::{Package, PackageSelection, Workspace};
+use crate::{relative_to, ResultExt, SnapResult};
+
+/// How often source files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// When each watched file was last modified.
+type Mtimes = BTreeMap<PathBuf, SystemTime>;
+
+/// Reruns the tests affected by each change to the sources of `packages`, and opens a review
+/// whenever snapshots fail. Runs until interrupted.
+pub fn watch(workspace: &Workspace,
+             packages: &[&Package],
+             cargo_args: &CargoArgs,
+             cwd: &Path)
+             -> SnapResult<()> {
+    let mut can_update = Term::stdout().is_term();
+    for package in packages {
+        can_update &= !package.config()?.is_strict();
+    }
+
+    let mut args = cargo_args.with(&PackageSelection::cargo_args(packages), &[]);
+    loop {
+        // a run that fails, say because a file was saved half edited, waits for the next change
+        if let Err(why) = run_and_review(workspace, packages, &args, can_update, cwd) {
+            println!("\nError: {}", why);
+        }
+
+        // don't rerun for anything written while the tests ran or during the review
+        let mut mtimes = scan(packages)?;
+
+        println!("\nWatching for changes, press Ctrl-C to stop...");
+
+        let changed = loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = scan(packages)?;
+            let changed = changed_files(&mtimes, &current);
+            mtimes = current;
+            if !changed.is_empty() {
+                break changed;
+            }
+        };
+
+        let names: Vec<String> = changed.iter().map(|path| relative_to(cwd, path)).collect();
+        println!("\n{} changed, rerunning affected tests...", names.join(", "));
+
+        args = cargo_args.with(&affected_args(packages, &changed), &[]);
+    }
+}
+
+fn run_and_review(workspace: &Workspace,
+                  packages: &[&Package],
+                  args: &[String],
+                  can_update: bool,
+                  cwd: &Path)
+                  -> SnapResult<()> {
+    let run = run_cargo_test(workspace, args, &[], false)?;
+    let suites = run.suites();
+    if !run.success && suites.is_empty() {
+        // the tests didn't build, and cargo's output has already been shown
+        return Ok(());
+    }
+
+    print_summary(&classify(&suites, &run.events, &recorded_keys(packages)?));
+    print_unrelated_failures(&unrelated_failures(&suites, &run.events));
+
+    let failed = failed_snapshots(run.events.clone());
+    if can_update && !failed.is_empty() {
+        let items = review_items(packages, &failed, cwd)?;
+        if !items.is_empty() && !review_and_apply(&items)? {
+            println!("\nLeft {} snapshot(s) unreviewed.", items.len());
+        }
+    }
+
+    Ok(())
+}
+
+/// The modification times of every Rust source file and manifest in `packages`.
+fn scan(packages: &[&Package]) -> SnapResult<Mtimes> {
+    let mut mtimes = Mtimes::new();
+
+    for package in packages {
+        for walk_result in WalkDir::new(package.manifest_dir()).into_iter().filter_entry(is_searched) {
+            let entry = walk_result.chain_err(|| "unable to traverse project directory")?;
+            if !is_watched(entry.path()) {
+                continue;
+            }
+
+            // files can disappear while we're looking, they'll show up as removed next time
+            if let Some(modified) = entry.metadata().ok().and_then(|m| m.modified().ok()) {
+                mtimes.insert(entry.path().to_owned(), modified);
+            }
+        }
+    }
+
+    Ok(mtimes)
+}
+
+fn is_watched(path: &Path) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    name.ends_with(".rs") || name == "Cargo.toml" || name == "snapshot.toml"
+}
+
+/// Files which were added, modified or removed between two scans.
+fn changed_files(before: &Mtimes, after: &Mtimes) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = after
+        .iter()
+        .filter(|&(path, modified)| before.get(path) != Some(modified))
+        .map(|(path, _)| path.clone())
+        .collect();
+
+    changed.extend(before.keys().filter(|path| !after.contains_key(*path)).cloned());
+    changed
+}
+
+/// The `cargo test` arguments for the tests that `changed` could affect. When only the roots of
+/// a package's test targets changed just those targets run, otherwise every package with a
+/// change runs all of its tests.
+fn affected_args(packages: &[&Package], changed: &[PathBuf]) -> Vec<String> {
+    let mut affected: BTreeMap<&str, (&Package, Vec<String>, bool)> = BTreeMap::new();
+
+    for path in changed {
+        let package = match packages
+            .iter()
+            .filter(|package| path.starts_with(package.manifest_dir()))
+            .max_by_key(|package| package.manifest_dir().components().count())
+        {
+            Some(package) => package,
+            None => continue,
+        };
+
+        let entry = affected
+            .entry(&package.name)
+            .or_insert_with(|| (*package, Vec::new(), false));
+        let test_target = package
+            .targets
+            .iter()
+            .find(|target| target.src_path == *path && target.kind.iter().any(|kind| kind == "test"));
+
+        match test_target {
+            Some(target) if !entry.1.contains(&target.name) => entry.1.push(target.name.clone()),
+            Some(_) => (),
+            None => entry.2 = true,
+        }
+    }
+
+    // `--test` applies to every package in the command, so only narrow down a single package
+    let narrow = affected.len() == 1;
+    let mut args = Vec::new();
+    for (_, (package, tests, whole_package)) in affected {
+        args.push("-p".to_owned());
+        args.push(package.name.clone());
+
+        if narrow && !whole_package {
+            for test in tests {
+                args.push("--test".to_owned());
+                args.push(test);
+            }
+        }
+    }
+    args
+}
+
+#[cfg(test)]
+mod watch_tests {
+    use super::{affected_args, changed_files, Mtimes};
+    use crate::workspace::{Package, Target};
+    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn package(name: &str, tests: &[&str]) -> Package {
+        Package {
+            id: format!("{} 0.1.0", name),
+            name: name.to_owned(),
+            manifest_path: PathBuf::from(format!("/ws/{}/Cargo.toml", name)),
+            targets: tests
+                .iter()
+                .map(|test| Target {
+                    name: test.to_string(),
+                    kind: vec!["test".to_owned()],
+                    src_path: PathBuf::from(format!("/ws/{}/tests/{}.rs", name, test)),
+                })
+                .collect(),
+        }
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn it_should_find_changed_files() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let before: Mtimes = vec![(PathBuf::from("a.rs"), at(1)), (PathBuf::from("b.rs"), at(1)),
+                                  (PathBuf::from("gone.rs"), at(1))]
+            .into_iter()
+            .collect();
+        let after: Mtimes = vec![(PathBuf::from("a.rs"), at(1)), (PathBuf::from("b.rs"), at(2)),
+                                 (PathBuf::from("new.rs"), at(1))]
+            .into_iter()
+            .collect();
+
+        assert_eq!(changed_files(&before, &after), paths(&["b.rs", "new.rs", "gone.rs"]));
+    }
+
+    #[test]
+    fn it_should_rerun_affected_tests() {
+        let app = package("app", &["simple", "second"]);
+        let core = package("core", &[]);
+        let packages = vec![&app, &core];
+
+        assert_eq!(affected_args(&packages, &paths(&["/ws/app/tests/simple.rs"])),
+                   vec!["-p", "app", "--test", "simple"]);
+        assert_eq!(affected_args(&packages,
+                                 &paths(&["/ws/app/tests/simple.rs", "/ws/app/src/lib.rs"])),
+                   vec!["-p", "app"]);
+        assert_eq!(affected_args(&packages,
+                                 &paths(&["/ws/app/tests/simple.rs", "/ws/core/src/lib.rs"])),
+                   vec!["-p", "app", "-p", "core"]);
+    }
+}
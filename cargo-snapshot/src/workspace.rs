@@ -0,0 +1,305 @@
+use std::path::{Path, PathBuf};
+
+use clap::{App, Arg, ArgMatches};
+use duct::cmd;
+use serde_derive::Deserialize;
+use snapshot::config::Config;
+
+use crate::{ResultExt, SnapResult};
+
+/// The packages in the current workspace, as described by `cargo metadata`.
+#[derive(Debug, PartialEq)]
+pub struct Workspace {
+    pub root: PathBuf,
+    pub target_dir: PathBuf,
+    pub members: Vec<Package>,
+    /// Members that cargo builds when run from a virtual manifest without `-p` or `--workspace`.
+    pub default_members: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Package {
+    pub id: String,
+    pub name: String,
+    pub manifest_path: PathBuf,
+    pub targets: Vec<Target>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Target {
+    pub name: String,
+    pub kind: Vec<String>,
+    pub src_path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    workspace_members: Vec<String>,
+    #[serde(default)]
+    workspace_default_members: Option<Vec<String>>,
+    workspace_root: PathBuf,
+    target_directory: PathBuf,
+}
+
+/// Which packages a subcommand applies to, chosen like `cargo test` does.
+#[derive(Debug, Default, PartialEq)]
+pub struct PackageSelection {
+    pub packages: Vec<String>,
+    pub workspace: bool,
+    pub exclude: Vec<String>,
+}
+
+impl Workspace {
+    pub fn load() -> SnapResult<Self> {
+        let output = cmd("cargo", ["metadata", "--format-version", "1", "--no-deps"])
+            .stdout_capture()
+            .run()
+            .chain_err(|| "unable to execute `cargo metadata`")?;
+
+        Workspace::parse(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    fn parse(metadata: &str) -> SnapResult<Self> {
+        let Metadata {
+            packages,
+            workspace_members,
+            workspace_default_members,
+            workspace_root,
+            target_directory,
+        } = serde_json::from_str(metadata)
+            .chain_err(|| "unable to parse the output of `cargo metadata`")?;
+
+        Ok(Workspace {
+            root: workspace_root,
+            target_dir: target_directory,
+            default_members: workspace_default_members
+                .unwrap_or_else(|| workspace_members.clone()),
+            members: packages
+                .into_iter()
+                .filter(|package| workspace_members.contains(&package.id))
+                .collect(),
+        })
+    }
+
+    /// The packages chosen by `selection`, run from `cwd`.
+    pub fn select(&self, selection: &PackageSelection, cwd: &Path) -> SnapResult<Vec<&Package>> {
+        for name in selection.packages.iter().chain(&selection.exclude) {
+            if !self.members.iter().any(|package| package.name == *name) {
+                bail!("package `{}` is not a member of the workspace", name);
+            }
+        }
+
+        let selected: Vec<&Package> = if !selection.packages.is_empty() {
+            self.members
+                .iter()
+                .filter(|package| selection.packages.contains(&package.name))
+                .collect()
+        } else if selection.workspace {
+            self.members
+                .iter()
+                .filter(|package| !selection.exclude.contains(&package.name))
+                .collect()
+        } else {
+            match self.package_containing(cwd) {
+                Some(package) => vec![package],
+                None => self
+                    .members
+                    .iter()
+                    .filter(|package| self.default_members.contains(&package.id))
+                    .collect(),
+            }
+        };
+
+        Ok(selected)
+    }
+
+    /// Where `cargo snapshot` has the library log the events of its latest test run.
+    pub fn event_log(&self) -> PathBuf {
+        self.target_dir.join("snapshot").join("events.jsonl")
+    }
+
+    /// The member whose directory is the closest ancestor of `path`.
+    pub fn package_containing(&self, path: &Path) -> Option<&Package> {
+        self.members
+            .iter()
+            .filter(|package| path.starts_with(package.manifest_dir()))
+            .max_by_key(|package| package.manifest_dir().components().count())
+    }
+
+    /// The member with its `Cargo.toml` in `manifest_dir`.
+    pub fn package_at(&self, manifest_dir: &Path) -> Option<&Package> {
+        self.members
+            .iter()
+            .find(|package| package.manifest_dir() == manifest_dir)
+    }
+}
+
+impl Package {
+    pub fn manifest_dir(&self) -> &Path {
+        self.manifest_path.parent().unwrap_or_else(|| Path::new(""))
+    }
+
+    pub fn config(&self) -> SnapResult<Config> {
+        match Config::load(self.manifest_dir()) {
+            Ok(config) => Ok(config),
+            Err(why) => bail!("unable to load snapshot config for `{}`: {}", self.name, why),
+        }
+    }
+
+    /// The target compiled as the crate named `crate_name`, which begins every snapshot key
+    /// recorded by its tests.
+    pub fn target_for_crate(&self, crate_name: &str) -> Option<&Target> {
+        self.targets
+            .iter()
+            .find(|target| target.name.replace('-', "_") == crate_name)
+    }
+}
+
+impl Target {
+    /// The `cargo test` arguments that build just this target.
+    pub fn cargo_args(&self) -> Vec<String> {
+        let kind = self.kind.first().map(String::as_str).unwrap_or_default();
+        match kind {
+            "lib" | "rlib" | "dylib" | "cdylib" | "staticlib" | "proc-macro" => vec!["--lib".to_owned()],
+            "bin" => vec!["--bin".to_owned(), self.name.clone()],
+            "test" => vec!["--test".to_owned(), self.name.clone()],
+            "example" => vec!["--example".to_owned(), self.name.clone()],
+            "bench" => vec!["--bench".to_owned(), self.name.clone()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl PackageSelection {
+    /// Adds `-p/--package`, `--workspace` and `--exclude` to a subcommand.
+    pub fn args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.arg(Arg::with_name("package")
+                    .short("p")
+                    .long("package")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Package to work on, may be repeated."))
+            .arg(Arg::with_name("workspace")
+                     .long("workspace")
+                     .help("Work on every package in the workspace."))
+            .arg(Arg::with_name("exclude")
+                     .long("exclude")
+                     .takes_value(true)
+                     .multiple(true)
+                     .number_of_values(1)
+                     .requires("workspace")
+                     .help("Package to leave out when using --workspace, may be repeated."))
+    }
+
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        let values = |name| {
+            matches
+                .values_of(name)
+                .map(|values| values.map(str::to_owned).collect())
+                .unwrap_or_default()
+        };
+
+        PackageSelection {
+            packages: values("package"),
+            workspace: matches.is_present("workspace"),
+            exclude: values("exclude"),
+        }
+    }
+
+    /// The `cargo test` arguments that select `packages`.
+    pub fn cargo_args(packages: &[&Package]) -> Vec<String> {
+        packages
+            .iter()
+            .flat_map(|package| vec!["-p".to_owned(), package.name.clone()])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod workspace_tests {
+    use super::{PackageSelection, Workspace};
+    use std::path::Path;
+
+    static METADATA: &str = r#"{
+        "packages": [
+            {
+                "id": "app 0.1.0",
+                "name": "app",
+                "manifest_path": "/ws/app/Cargo.toml",
+                "targets": [
+                    { "name": "app", "kind": ["bin"], "src_path": "/ws/app/src/main.rs" },
+                    { "name": "end-to-end", "kind": ["test"], "src_path": "/ws/app/tests/end-to-end.rs" }
+                ]
+            },
+            {
+                "id": "core 0.1.0",
+                "name": "core",
+                "manifest_path": "/ws/core/Cargo.toml",
+                "targets": [
+                    { "name": "core", "kind": ["lib"], "src_path": "/ws/core/src/lib.rs" }
+                ]
+            }
+        ],
+        "workspace_members": ["app 0.1.0", "core 0.1.0"],
+        "workspace_default_members": ["core 0.1.0"],
+        "workspace_root": "/ws",
+        "target_directory": "/ws/target"
+    }"#;
+
+    fn names(packages: &[&super::Package]) -> Vec<String> {
+        packages.iter().map(|p| p.name.clone()).collect()
+    }
+
+    #[test]
+    fn it_should_select_packages_like_cargo() {
+        let workspace = Workspace::parse(METADATA).unwrap();
+        let select = |selection, cwd: &str| {
+            names(&workspace.select(&selection, Path::new(cwd)).unwrap())
+        };
+
+        assert_eq!(select(PackageSelection::default(), "/ws/app/src"), vec!["app"]);
+        assert_eq!(select(PackageSelection::default(), "/ws"), vec!["core"]);
+        assert_eq!(
+            select(
+                PackageSelection {
+                    workspace: true,
+                    exclude: vec!["core".to_owned()],
+                    ..PackageSelection::default()
+                },
+                "/ws/core"
+            ),
+            vec!["app"]
+        );
+        assert_eq!(
+            select(
+                PackageSelection {
+                    packages: vec!["core".to_owned()],
+                    ..PackageSelection::default()
+                },
+                "/ws/app"
+            ),
+            vec!["core"]
+        );
+
+        let unknown = PackageSelection {
+            packages: vec!["nope".to_owned()],
+            ..PackageSelection::default()
+        };
+        assert!(workspace.select(&unknown, Path::new("/ws")).is_err());
+    }
+
+    #[test]
+    fn it_should_find_targets_by_crate_name() {
+        let workspace = Workspace::parse(METADATA).unwrap();
+        let app = workspace.package_at(Path::new("/ws/app")).unwrap();
+
+        assert_eq!(
+            app.target_for_crate("end_to_end").unwrap().cargo_args(),
+            vec!["--test", "end-to-end"]
+        );
+        assert_eq!(app.target_for_crate("app").unwrap().cargo_args(), vec!["--bin", "app"]);
+        assert_eq!(app.target_for_crate("core"), None);
+    }
+}
@@ -7,14 +7,16 @@ extern crate proc_macro;
 extern crate quote;
 
 use proc_macro::TokenStream;
-use syn::Item;
+use quote::ToTokens;
+use syn::{Item, ItemStruct, Lit, Meta, NestedMeta};
 
 #[proc_macro_attribute]
-pub fn snapshot(_: TokenStream, function: TokenStream) -> TokenStream {
+pub fn snapshot(args: TokenStream, function: TokenStream) -> TokenStream {
+    let options = parse_options(args);
     let mut inner_fn: Item = syn::parse(function.into()).unwrap();
 
     // swap the inner/outer function names in the Item
-    let mut fn_item = match inner_fn {
+    let fn_item = match inner_fn {
         Item::Fn(ref mut item) => item,
         _ => panic!("#[snapshot] can only be applied to functions"),
     };
@@ -42,16 +44,49 @@ pub fn snapshot(_: TokenStream, function: TokenStream) -> TokenStream {
 
             let snapshot = ::snapshot::Snapshot::new(
                 file, module_path, test_function, recorded_value,
-            );
+            )#options;
 
-            let manifest_dir = env!("CARGO_MANIFEST_DIR");
-            if let Ok(_) = ::std::env::var("UPDATE_SNAPSHOTS") {
-                snapshot.update_snapshot(manifest_dir);
-            } else {
-                snapshot.check_snapshot(manifest_dir);
-            }
+            snapshot.assert_snapshot(env!("CARGO_MANIFEST_DIR"));
         }
     };
 
     output.into()
 }
+
+/// Turns the `#[snapshot(...)]` arguments into builder calls on the generated `Snapshot`.
+fn parse_options(args: TokenStream) -> impl ToTokens {
+    // syn can't parse a bare argument list, so parse it as an attribute on a throwaway item
+    let holder: ItemStruct = syn::parse_str(&format!("#[snapshot({})] struct Holder;", args))
+        .expect("#[snapshot] arguments must be a comma-separated list");
+
+    let args = match holder.attrs[0].interpret_meta() {
+        Some(Meta::List(list)) => list.nested,
+        _ => panic!("#[snapshot] arguments must be a comma-separated list"),
+    };
+
+    let mut unordered_everywhere = false;
+    let mut unordered_at = Vec::new();
+
+    for arg in args {
+        match arg {
+            NestedMeta::Meta(Meta::Word(ref ident)) if ident == "unordered" => {
+                unordered_everywhere = true;
+            }
+            NestedMeta::Meta(Meta::NameValue(ref pair)) if pair.ident == "unordered" => {
+                match pair.lit {
+                    Lit::Str(ref selector) => unordered_at.push(selector.value()),
+                    _ => panic!("#[snapshot(unordered = ...)] expects a string selector"),
+                }
+            }
+            _ => panic!("unsupported #[snapshot] argument"),
+        }
+    }
+
+    if unordered_everywhere {
+        quote! { .unordered_everywhere() }
+    } else if !unordered_at.is_empty() {
+        quote! { .unordered_at(&[#(#unordered_at),*]) }
+    } else {
+        quote! {}
+    }
+}
@@ -0,0 +1,320 @@
+use serde_derive::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::selector::Selector;
+
+pub static CONFIG_FILE_NAME: &str = "snapshot.toml";
+pub static SNAPSHOT_DIR_VAR: &str = "SNAPSHOT_DIR";
+pub static SNAPSHOT_EXTENSION_VAR: &str = "SNAPSHOT_EXTENSION";
+pub static UPDATE_SNAPSHOTS_VAR: &str = "UPDATE_SNAPSHOTS";
+pub static UPDATE_SNAPSHOTS_FILTER_VAR: &str = "UPDATE_SNAPSHOTS_FILTER";
+pub static PENDING_EXTENSION: &str = "pending";
+pub static CI_VAR: &str = "CI";
+pub static SNAPSHOT_CI_VAR: &str = "SNAPSHOT_CI";
+
+/// Project-level settings, read from `snapshot.toml` next to the crate's `Cargo.toml` or from
+/// `[package.metadata.snapshot]` in `Cargo.toml` itself.
+///
+/// ```toml
+/// format = "pretty"        # or "compact"
+/// dir = "tests/snapshots"  # a single root for snapshot files, mirroring the source tree
+/// extension = "snap"
+/// update = "no"            # "new", "always" or "pending", when `UPDATE_SNAPSHOTS` isn't set
+/// strict = false           # refuse to write any snapshots, always on in CI
+/// diff = "structural"      # or "debug"
+///
+/// [redactions]
+/// ".created_at" = "[timestamp]"
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub format: Format,
+    pub dir: Option<PathBuf>,
+    pub extension: Option<String>,
+    pub update: UpdateMode,
+    pub strict: Option<bool>,
+    pub diff: DiffStyle,
+    pub redactions: BTreeMap<String, serde_json::Value>,
+    /// The only snapshot keys `update` applies to, from `UPDATE_SNAPSHOTS_FILTER`. Every other
+    /// snapshot is just checked.
+    #[serde(skip)]
+    pub update_filter: Option<BTreeSet<String>>,
+    /// Whether we're running in CI, see `is_ci`.
+    #[serde(skip)]
+    pub ci: bool,
+}
+
+/// How snapshot files are written.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Pretty,
+    Compact,
+}
+
+/// Whether a snapshot test records its value instead of checking it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateMode {
+    /// Check against the recorded snapshot, failing if it's missing. Never writes.
+    #[default]
+    No,
+    /// Record missing snapshots, but still fail on mismatches.
+    New,
+    /// Record the value, overwriting any existing snapshot.
+    Always,
+    /// Write missing or mismatched values to a `.pending` side file for review, and fail.
+    Pending,
+}
+
+impl FromStr for UpdateMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode.trim() {
+            "no" | "0" | "false" => Ok(UpdateMode::No),
+            "new" => Ok(UpdateMode::New),
+            // any other value used to mean "update everything", so keep accepting the common ones
+            "always" | "" | "1" | "true" | "yes" => Ok(UpdateMode::Always),
+            "pending" => Ok(UpdateMode::Pending),
+            other => Err(format!(
+                "unknown update mode {:?}, expected one of `no`, `new`, `always` or `pending`",
+                other
+            )),
+        }
+    }
+}
+
+/// How mismatches are reported in test failures.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffStyle {
+    /// List the changed, added and removed paths of the serialized values.
+    #[default]
+    Structural,
+    /// A line diff of the values' `Debug` output.
+    Debug,
+}
+
+impl Config {
+    /// Reads the project config for the crate in `manifest_dir`, applying environment overrides.
+    pub fn load(manifest_dir: &Path) -> Result<Self, String> {
+        let mut config = Config::read_file(manifest_dir)?;
+
+        if let Some(root) = env::var_os(SNAPSHOT_DIR_VAR) {
+            if !root.is_empty() {
+                config.dir = Some(PathBuf::from(root));
+            }
+        }
+
+        if let Ok(extension) = env::var(SNAPSHOT_EXTENSION_VAR) {
+            if !extension.is_empty() {
+                config.extension = Some(extension);
+            }
+        }
+
+        if let Some(mode) = env::var_os(UPDATE_SNAPSHOTS_VAR) {
+            config.update = mode
+                .to_str()
+                .unwrap_or_default()
+                .parse()
+                .map_err(|why| format!("invalid {}: {}", UPDATE_SNAPSHOTS_VAR, why))?;
+        }
+
+        if let Some(keys) = env::var_os(UPDATE_SNAPSHOTS_FILTER_VAR) {
+            let keys = keys.to_str().unwrap_or_default();
+            config.update_filter = Some(
+                keys.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_owned)
+                    .collect(),
+            );
+        }
+
+        config.ci = is_ci();
+
+        Ok(config)
+    }
+
+    /// The update mode for the snapshot with `key`, taking `update_filter` into account.
+    pub fn update_mode_for(&self, key: &str) -> UpdateMode {
+        match self.update_filter {
+            Some(ref keys) if !keys.contains(key) => UpdateMode::No,
+            _ => self.update,
+        }
+    }
+
+    /// Whether snapshot writes are forbidden, either explicitly or because we're in CI. A
+    /// committed `strict = false` doesn't turn writes back on in CI, only `SNAPSHOT_CI=0` does.
+    pub fn is_strict(&self) -> bool {
+        self.ci || self.strict.unwrap_or(false)
+    }
+
+    fn read_file(manifest_dir: &Path) -> Result<Self, String> {
+        let config_path = manifest_dir.join(CONFIG_FILE_NAME);
+        match fs::read_to_string(&config_path) {
+            Ok(contents) => {
+                return toml::from_str(&contents)
+                    .map_err(|why| format!("unable to parse {:?}: {}", config_path, why));
+            }
+            Err(ref why) if why.kind() == ErrorKind::NotFound => (),
+            Err(why) => return Err(format!("unable to read {:?}: {}", config_path, why)),
+        }
+
+        let manifest_path = manifest_dir.join("Cargo.toml");
+        let manifest: toml::Value = match fs::read_to_string(&manifest_path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|why| format!("unable to parse {:?}: {}", manifest_path, why))?,
+            Err(ref why) if why.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(why) => return Err(format!("unable to read {:?}: {}", manifest_path, why)),
+        };
+
+        let metadata = manifest
+            .get("package")
+            .and_then(|package| package.get("metadata"))
+            .and_then(|metadata| metadata.get("snapshot"));
+
+        match metadata {
+            Some(metadata) => metadata.clone().try_into().map_err(|why| {
+                format!(
+                    "unable to parse [package.metadata.snapshot] in {:?}: {}",
+                    manifest_path, why
+                )
+            }),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn layout(&self) -> Layout {
+        let mut layout = Layout {
+            root: self.dir.clone(),
+            ..Layout::default()
+        };
+
+        if let Some(ref extension) = self.extension {
+            let extension = extension.trim_start_matches('.');
+            if !extension.is_empty() {
+                layout.extension = extension.to_owned();
+            }
+        }
+
+        layout
+    }
+
+    /// The configured redactions, replacing whatever is found at each selector.
+    pub fn redactions(&self) -> Result<Vec<(Selector, serde_json::Value)>, String> {
+        self.redactions
+            .iter()
+            .map(|(selector, replacement)| Ok((selector.parse()?, replacement.clone())))
+            .collect()
+    }
+}
+
+/// Whether we're running in CI: `SNAPSHOT_CI` decides if it's set, otherwise `CI` does.
+pub fn is_ci() -> bool {
+    fn enabled(value: &str) -> bool {
+        !matches!(value.trim(), "" | "0" | "false" | "no")
+    }
+
+    match env::var(SNAPSHOT_CI_VAR) {
+        Ok(value) => enabled(&value),
+        Err(_) => env::var(CI_VAR)
+            .map(|value| enabled(&value))
+            .unwrap_or(false),
+    }
+}
+
+/// Where snapshot files are stored, relative to the crate's manifest directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    /// A single directory holding every snapshot file, mirroring the source tree beneath it.
+    /// When unset, each source file gets a `__snapshots__` directory next to it.
+    pub root: Option<PathBuf>,
+    /// Extension appended to the source file name, without the leading dot.
+    pub extension: String,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            root: None,
+            extension: "snap".to_owned(),
+        }
+    }
+}
+
+impl Layout {
+    /// The directory holding snapshots for `source_file`, given as path components relative to
+    /// the manifest directory (like `Snapshot::file`).
+    pub fn snap_dir(&self, source_file: &[String]) -> PathBuf {
+        let source_dirs = &source_file[..source_file.len().saturating_sub(1)];
+
+        let mut dir = match self.root {
+            Some(ref root) => root.clone(),
+            None => PathBuf::new(),
+        };
+
+        for directory in source_dirs {
+            dir.push(directory);
+        }
+
+        if self.root.is_none() {
+            dir.push("__snapshots__");
+        }
+
+        dir
+    }
+
+    /// The name of the snapshot file for `source_file`, e.g. `lib.rs.snap`.
+    pub fn snap_file_name(&self, source_file: &[String]) -> String {
+        let mut filename = source_file.last().cloned().unwrap_or_default();
+        filename.push('.');
+        filename.push_str(&self.extension);
+        filename
+    }
+
+    pub fn is_snap_file(&self, path: &Path) -> bool {
+        path.extension() == Some(OsStr::new(&self.extension))
+    }
+
+    /// The manifest directory that `snap_file` was written beneath, given the source file its
+    /// snapshots were recorded from. Returns `None` if the layout doesn't match.
+    pub fn manifest_dir_of(&self, snap_file: &Path, source_file: &[String]) -> Option<PathBuf> {
+        let relative = self
+            .snap_dir(source_file)
+            .join(self.snap_file_name(source_file));
+
+        if relative.is_absolute() || !snap_file.ends_with(&relative) {
+            return None;
+        }
+
+        let mut manifest_dir = snap_file.to_path_buf();
+        for _ in relative.components() {
+            manifest_dir.pop();
+        }
+        Some(manifest_dir)
+    }
+
+    /// The side file next to `snap_file` holding snapshots waiting for review.
+    pub fn pending_path(snap_file: &Path) -> PathBuf {
+        let mut pending = snap_file.as_os_str().to_owned();
+        pending.push(".");
+        pending.push(PENDING_EXTENSION);
+        PathBuf::from(pending)
+    }
+
+    pub fn is_pending_file(&self, path: &Path) -> bool {
+        path.extension() == Some(OsStr::new(PENDING_EXTENSION))
+            && path
+                .file_stem()
+                .is_some_and(|stem| self.is_snap_file(Path::new(stem)))
+    }
+}
@@ -0,0 +1,161 @@
+use serde_json::Value;
+use std::fmt;
+
+use crate::selector::{display_path, Segment};
+
+/// A single difference between a recorded value and a new one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Changed {
+        path: Vec<Segment>,
+        old: Value,
+        new: Value,
+    },
+    Added {
+        path: Vec<Segment>,
+        new: Value,
+    },
+    Removed {
+        path: Vec<Segment>,
+        old: Value,
+    },
+}
+
+impl Change {
+    pub fn path(&self) -> &[Segment] {
+        match self {
+            Change::Changed { path, .. }
+            | Change::Added { path, .. }
+            | Change::Removed { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Changed { path, old, new } => {
+                write!(f, "~ {}: {} -> {}", display_path(path), old, new)
+            }
+            Change::Added { path, new } => write!(f, "+ {}: {}", display_path(path), new),
+            Change::Removed { path, old } => write!(f, "- {}: {}", display_path(path), old),
+        }
+    }
+}
+
+/// Walks both trees and lists every path whose value was changed, added or removed.
+///
+/// Arrays are compared index by index, so an element inserted in the middle shows up as a change
+/// to every following index.
+pub fn diff_values(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_at(old, new, &mut Vec::new(), &mut changes);
+    changes
+}
+
+/// Renders changes one per line, for inclusion in assertion failures.
+pub fn render(changes: &[Change]) -> String {
+    let mut rendered = String::new();
+    for change in changes {
+        rendered.push_str(&format!("  {}\n", change));
+    }
+    rendered
+}
+
+fn diff_at(old: &Value, new: &Value, path: &mut Vec<Segment>, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            for (key, old_field) in old_fields {
+                path.push(Segment::Key(key.clone()));
+                match new_fields.get(key) {
+                    Some(new_field) => diff_at(old_field, new_field, path, changes),
+                    None => changes.push(Change::Removed {
+                        path: path.clone(),
+                        old: old_field.clone(),
+                    }),
+                }
+                path.pop();
+            }
+
+            for (key, new_field) in new_fields {
+                if !old_fields.contains_key(key) {
+                    path.push(Segment::Key(key.clone()));
+                    changes.push(Change::Added {
+                        path: path.clone(),
+                        new: new_field.clone(),
+                    });
+                    path.pop();
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            let longest = old_items.len().max(new_items.len());
+            for idx in 0..longest {
+                path.push(Segment::Index(idx));
+                match (old_items.get(idx), new_items.get(idx)) {
+                    (Some(old_item), Some(new_item)) => diff_at(old_item, new_item, path, changes),
+                    (Some(old_item), None) => changes.push(Change::Removed {
+                        path: path.clone(),
+                        old: old_item.clone(),
+                    }),
+                    (None, Some(new_item)) => changes.push(Change::Added {
+                        path: path.clone(),
+                        new: new_item.clone(),
+                    }),
+                    (None, None) => unreachable!(),
+                }
+                path.pop();
+            }
+        }
+        (old, new) => {
+            if old != new {
+                changes.push(Change::Changed {
+                    path: path.clone(),
+                    old: old.clone(),
+                    new: new.clone(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use super::{diff_values, render};
+    use serde_json::json;
+
+    #[test]
+    fn identical_values_have_no_changes() {
+        let value = json!({ "items": [1, 2, { "name": "a" }] });
+
+        assert!(diff_values(&value, &value).is_empty());
+    }
+
+    #[test]
+    fn it_should_report_nested_paths() {
+        let old = json!({ "items": [{ "name": "a" }, { "name": "b", "gone": true }] });
+        let new = json!({ "items": [{ "name": "a" }, { "name": "c" }, 3], "extra": null });
+
+        assert_eq!(
+            render(&diff_values(&old, &new)),
+            "  - .items[1].gone: true\n  \
+             ~ .items[1].name: \"b\" -> \"c\"\n  \
+             + .items[2]: 3\n  \
+             + .extra: null\n"
+        );
+    }
+
+    #[test]
+    fn it_should_report_type_changes_at_the_root() {
+        let changes = diff_values(&json!([1]), &json!({ "odd key": 1 }));
+
+        assert_eq!(render(&changes), "  ~ .: [1] -> {\"odd key\":1}\n");
+    }
+
+    #[test]
+    fn it_should_quote_unusual_keys() {
+        let changes = diff_values(&json!({ "odd key": 1 }), &json!({ "odd key": 2 }));
+
+        assert_eq!(render(&changes), "  ~ [\"odd key\"]: 1 -> 2\n");
+    }
+}
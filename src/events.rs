@@ -0,0 +1,104 @@
+use fs2::FileExt;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Names a file that every snapshot assertion appends an `Event` to, as a line of JSON.
+pub static SNAPSHOT_EVENT_LOG_VAR: &str = "SNAPSHOT_EVENT_LOG";
+
+static OS_LOCK_LOG_FAIL: &str = "Your OS failed to lock the snapshot event log!";
+
+/// What a snapshot assertion did, for tools like `cargo snapshot` to read after a test run.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Event {
+    /// The snapshot's key in its file, `module_path::test_function`.
+    pub key: String,
+    pub manifest_dir: PathBuf,
+    /// The snapshot file, relative to `manifest_dir`.
+    pub file: PathBuf,
+    /// The source file of the test, relative to `manifest_dir`.
+    pub source_file: PathBuf,
+    pub outcome: Outcome,
+    /// Hash of the recorded value, if there was one.
+    pub old_hash: Option<String>,
+    /// Hash of the value the test produced.
+    pub new_hash: Option<String>,
+    /// The value the test produced, when it didn't match or had no recorded snapshot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_value: Option<serde_json::Value>,
+    /// Why the assertion failed.
+    pub message: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Matched,
+    Mismatched,
+    Missing,
+    Written,
+}
+
+/// The event log for this test run, if one was requested.
+pub fn log_path() -> Option<PathBuf> {
+    env::var_os(SNAPSHOT_EVENT_LOG_VAR)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Appends an event to the log, locking it so concurrent tests don't interleave their lines.
+pub fn append(log: &Path, event: &Event) {
+    let mut line = match serde_json::to_string(event) {
+        Ok(line) => line,
+        Err(why) => panic!("Unable to serialize snapshot event: {:?}", why),
+    };
+    line.push('\n');
+
+    let mut file = match OpenOptions::new().create(true).append(true).open(log) {
+        Ok(f) => f,
+        Err(why) => panic!(
+            "Unable to open snapshot event log {:?}: {:?}",
+            log,
+            why.kind()
+        ),
+    };
+
+    file.lock_exclusive().expect(OS_LOCK_LOG_FAIL);
+    if let Err(why) = file.write_all(line.as_bytes()) {
+        panic!(
+            "Unable to write snapshot event log {:?}: {:?}",
+            log,
+            why.kind()
+        );
+    }
+    let _ = file.unlock();
+}
+
+/// Reads every event in a log, skipping lines that were cut off.
+pub fn read_log(log: &Path) -> Result<Vec<Event>, String> {
+    let contents = match fs::read_to_string(log) {
+        Ok(contents) => contents,
+        Err(ref why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(why) => return Err(format!("unable to read {:?}: {}", log, why)),
+    };
+
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// A short hash of a value, for telling whether two values differ. This is FNV-1a of the value's
+/// JSON, which unlike std's hashers stays the same across builds, so logs from different runs can
+/// be compared.
+pub fn hash_value(value: &serde_json::Value) -> String {
+    let hash = value
+        .to_string()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
@@ -1,5 +1,16 @@
 pub use snapshot_proc_macro::snapshot;
 
+pub mod config;
+pub mod diff;
+pub mod events;
+mod normalize;
+pub mod selector;
+
+use crate::config::{Config, DiffStyle, Format, Layout, UpdateMode};
+use crate::events::{Event, Outcome};
+pub use crate::normalize::Unordered;
+use crate::selector::Selector;
+
 use serde_derive::{Deserialize, Serialize};
 
 use fs2::FileExt;
@@ -7,13 +18,13 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::collections::BTreeMap;
 use std::fmt::Debug;
-use std::fs::{create_dir_all, File, OpenOptions};
+use std::fs::{create_dir_all, remove_file, File, OpenOptions};
 use std::io::prelude::*;
 use std::io::SeekFrom;
 use std::io::{BufReader, BufWriter};
 use std::path::{Path, PathBuf};
 
-use pretty_assertions::assert_eq;
+use pretty_assertions::Comparison;
 
 static OS_LOCK_FILE_FAIL: &str = "Your OS failed to lock the '.snap' file!";
 static OS_CLONE_FILE_FAIL: &str = "Your OS Failed to clone file handle";
@@ -29,6 +40,8 @@ pub struct Snapshot<S: Snapable> {
     pub module_path: String,
     pub test_function: String,
     pub recorded_value: S,
+    #[serde(skip)]
+    unordered: Unordered,
 }
 
 impl<S> Snapshot<S>
@@ -49,79 +62,291 @@ where
             module_path,
             test_function,
             recorded_value,
+            unordered: Unordered::Nowhere,
+        }
+    }
+
+    /// Compare and record every array in the value as an unordered multiset.
+    pub fn unordered_everywhere(mut self) -> Self {
+        self.unordered = Unordered::Everywhere;
+        self
+    }
+
+    /// Compare and record the arrays matched by `selectors` as unordered multisets.
+    pub fn unordered_at(mut self, selectors: &[&str]) -> Self {
+        let selectors = selectors
+            .iter()
+            .map(|s| match s.parse::<Selector>() {
+                Ok(selector) => selector,
+                Err(why) => panic!("Unable to use unordered selector: {}", why),
+            })
+            .collect();
+
+        self.unordered = Unordered::At(selectors);
+        self
+    }
+
+    /// Checks or records the snapshot, depending on `UPDATE_SNAPSHOTS`, `UPDATE_SNAPSHOTS_FILTER`
+    /// and the project config.
+    pub fn assert_snapshot(&self, manifest_dir: &str) {
+        let config = load_config(manifest_dir);
+
+        match config.update_mode_for(&self.module_key()) {
+            UpdateMode::No => self.check_snapshot(manifest_dir),
+            UpdateMode::Always => self.update_snapshot(manifest_dir),
+            UpdateMode::New => match self.verify(manifest_dir, &config) {
+                (_, Err(Failure::Missing(_))) if !config.is_strict() => {
+                    self.update_snapshot(manifest_dir)
+                }
+                (old_hash, result) => {
+                    self.report(manifest_dir, &config, old_hash, &result);
+                    self.fail_on(&config, result);
+                }
+            },
+            UpdateMode::Pending => {
+                let spec = self.path(manifest_dir, &config.layout()).pending();
+                let (old_hash, result) = self.verify(manifest_dir, &config);
+                self.report(manifest_dir, &config, old_hash, &result);
+
+                match result {
+                    Ok(()) => self.remove_from(&spec, &config),
+                    Err(Failure::Missing(why)) | Err(Failure::Mismatched(why)) => {
+                        if config.is_strict() {
+                            panic!("{}", why);
+                        }
+
+                        self.write_to(manifest_dir, &config, &spec);
+                        panic!(
+                            "{}\nWrote pending snapshot to {:?}, review it with `cargo snapshot update`",
+                            why, spec.relative_path
+                        );
+                    }
+                }
+            }
         }
     }
 
     pub fn check_snapshot(&self, manifest_dir: &str) {
+        let config = load_config(manifest_dir);
+        let (old_hash, result) = self.verify(manifest_dir, &config);
+
+        self.report(manifest_dir, &config, old_hash, &result);
+        self.fail_on(&config, result);
+    }
+
+    fn fail_on(&self, config: &Config, result: Result<(), Failure>) {
+        match result {
+            Ok(()) => (),
+            Err(Failure::Missing(why)) if config.is_strict() => self.missing_in_strict_mode(&why),
+            Err(Failure::Missing(why)) | Err(Failure::Mismatched(why)) => panic!("{}", why),
+        }
+    }
+
+    fn missing_in_strict_mode(&self, why: &str) -> ! {
+        panic!(
+            "Snapshot {:?} is not recorded, run `cargo snapshot update` locally and commit it.\n{}",
+            self.module_key(),
+            why
+        );
+    }
+
+    pub fn update_snapshot(&self, manifest_dir: &str) {
+        let config = load_config(manifest_dir);
+        let spec = self.path(manifest_dir, &config.layout());
+
+        let old_hash = events::log_path().and_then(|_| {
+            self.find_recorded(manifest_dir, &config)
+                .ok()
+                .map(|recorded| events::hash_value(&recorded.recorded_value))
+        });
+
+        self.write_to(manifest_dir, &config, &spec);
+        self.log_event(manifest_dir, &config, Outcome::Written, old_hash, None);
+
+        // a pending value for this snapshot is out of date once a new one is recorded
+        self.remove_from(&spec.pending(), &config);
+    }
+
+    /// Compares against the recorded snapshot, also returning the hash of the recorded value.
+    fn verify(&self, manifest_dir: &str, config: &Config) -> (Option<String>, Result<(), Failure>) {
+        match self.find_recorded(manifest_dir, config) {
+            Ok(recorded) => {
+                let old_hash = events::hash_value(&recorded.recorded_value);
+                let result = self.compare(recorded, config).map_err(Failure::Mismatched);
+                (Some(old_hash), result)
+            }
+            Err(why) => (None, Err(Failure::Missing(why))),
+        }
+    }
+
+    fn report(
+        &self,
+        manifest_dir: &str,
+        config: &Config,
+        old_hash: Option<String>,
+        result: &Result<(), Failure>,
+    ) {
+        let (outcome, why) = match result {
+            Ok(()) => (Outcome::Matched, None),
+            Err(Failure::Missing(why)) => (Outcome::Missing, Some(why.as_str())),
+            Err(Failure::Mismatched(why)) => (Outcome::Mismatched, Some(why.as_str())),
+        };
+
+        self.log_event(manifest_dir, config, outcome, old_hash, why);
+    }
+
+    /// Appends to the event log named by `SNAPSHOT_EVENT_LOG`, if it's set.
+    fn log_event(
+        &self,
+        manifest_dir: &str,
+        config: &Config,
+        outcome: Outcome,
+        old_hash: Option<String>,
+        why: Option<&str>,
+    ) {
+        let log = match events::log_path() {
+            Some(log) => log,
+            None => return,
+        };
+
+        let new_value = self.create_deserializable(config).recorded_value;
+        let new_hash = events::hash_value(&new_value);
+        let failed = outcome == Outcome::Mismatched || outcome == Outcome::Missing;
+        let event = Event {
+            key: self.module_key(),
+            manifest_dir: PathBuf::from(manifest_dir),
+            file: self.path(manifest_dir, &config.layout()).relative_path,
+            source_file: self.file.iter().collect(),
+            outcome,
+            old_hash,
+            new_hash: Some(new_hash),
+            new_value: if failed { Some(new_value) } else { None },
+            message: why.map(str::to_owned),
+        };
+
+        events::append(&log, &event);
+    }
+
+    fn find_recorded(
+        &self,
+        manifest_dir: &str,
+        config: &Config,
+    ) -> Result<Snapshot<serde_json::Value>, String> {
         let SnapFileSpec {
             absolute_path,
             relative_path,
             ..
-        } = self.path(manifest_dir);
+        } = self.path(manifest_dir, &config.layout());
 
         let snap_file = match File::open(&absolute_path) {
             Ok(f) => f,
-            Err(why) => panic!(
-                "Unable to open snapshot file {:?}: {:?}",
-                relative_path,
-                why.kind()
-            ),
+            Err(why) => {
+                return Err(format!(
+                    "Unable to open snapshot file {:?}: {:?}",
+                    relative_path,
+                    why.kind()
+                ))
+            }
         };
 
         let mut module_snapshots = parse_snaps_from_file(&snap_file, &relative_path);
 
         let snap_key = self.module_key();
-        let previous_snapshot = match module_snapshots.remove(&snap_key) {
-            Some(s) => s,
-            None => panic!(
+        match module_snapshots.remove(&snap_key) {
+            Some(s) => Ok(s),
+            None => Err(format!(
                 "Unable to find snapshot for test {:?} in {:?}",
                 snap_key, relative_path
-            ),
-        };
+            )),
+        }
+    }
 
+    fn compare(
+        &self,
+        previous: Snapshot<serde_json::Value>,
+        config: &Config,
+    ) -> Result<(), String> {
         let Snapshot {
             recorded_value,
             file,
             module_path,
             test_function,
-        } = previous_snapshot;
-
-        match serde_json::from_value(recorded_value) {
-            Ok(recorded_value) => {
-                assert_eq!(
-                    self.file, file,
-                    "Filename for snapshot test function doesn't match recorded one"
-                );
-
-                assert_eq!(
-                    self.module_path, module_path,
-                    "Module paths for snapshot test function doesn't match recorded one"
-                );
-
-                assert_eq!(
-                    self.test_function, test_function,
-                    "Test function name doesn't match recorded one"
-                );
-
-                assert_eq!(
-                    self.recorded_value, recorded_value,
-                    "Test output doesn't match recorded snapshot!"
-                );
+            ..
+        } = previous;
+
+        expect_eq(
+            &self.file,
+            &file,
+            "Filename for snapshot test function doesn't match recorded one",
+        )?;
+
+        expect_eq(
+            &self.module_path,
+            &module_path,
+            "Module paths for snapshot test function doesn't match recorded one",
+        )?;
+
+        expect_eq(
+            &self.test_function,
+            &test_function,
+            "Test function name doesn't match recorded one",
+        )?;
+
+        let mut expected = recorded_value;
+        self.normalize(&mut expected, config);
+        let actual = self.create_deserializable(config).recorded_value;
+
+        // Rewritten values may no longer deserialize as the test's type, so they're compared
+        //  as JSON instead.
+        if self.unordered == Unordered::Nowhere && config.redactions.is_empty() {
+            let recorded: S = match <S as serde::Deserialize>::deserialize(&expected) {
+                Ok(recorded) => recorded,
+                Err(why) => {
+                    return Err(format!(
+                        "Unable to parse existing snapshot as correct type: {:?}",
+                        why
+                    ))
+                }
+            };
+
+            if self.recorded_value != recorded {
+                return Err(describe_mismatch(
+                    config,
+                    &self.recorded_value,
+                    &recorded,
+                    &expected,
+                    &actual,
+                ));
             }
-            Err(why) => panic!(
-                "Unable to parse existing snapshot as correct type: {:?}",
-                why
-            ),
+        } else if actual != expected {
+            return Err(describe_mismatch(
+                config, &actual, &expected, &expected, &actual,
+            ));
         }
+
+        Ok(())
     }
 
-    pub fn update_snapshot(&self, manifest_dir: &str) {
+    fn write_to(&self, manifest_dir: &str, config: &Config, spec: &SnapFileSpec) {
+        if config.is_strict() {
+            let reason = if config.ci {
+                "snapshots can't be written in CI"
+            } else {
+                "writes are disabled by `strict` in the snapshot config"
+            };
+
+            panic!(
+                "Refusing to write snapshot {:?}: {}",
+                self.module_key(),
+                reason
+            );
+        }
+
         let SnapFileSpec {
             dir: snap_dir,
             absolute_path,
             relative_path,
-            ..
-        } = self.path(manifest_dir);
+        } = spec;
 
         let mut dir_to_create = PathBuf::from(manifest_dir);
         dir_to_create.push(snap_dir.clone());
@@ -136,9 +361,10 @@ where
 
         let mut file = match OpenOptions::new()
             .create(true)
+            .truncate(false)
             .read(true)
             .write(true)
-            .open(&absolute_path)
+            .open(absolute_path)
         {
             Ok(f) => f,
             Err(why) => panic!(
@@ -150,19 +376,47 @@ where
 
         file.lock_exclusive().expect(OS_LOCK_FILE_FAIL);
 
-        let mut existing_snaps: SnapFileContents = parse_snaps_from_file(&file, &relative_path);
+        let mut existing_snaps: SnapFileContents = parse_snaps_from_file(&file, relative_path);
 
         // Now we need to update the particular snapshot we care about
-        existing_snaps.insert(self.module_key(), self.create_deserializable());
+        existing_snaps.insert(self.module_key(), self.create_deserializable(config));
 
-        write_snaps_to_file(&mut file, &existing_snaps, &relative_path);
+        write_snaps_to_file(&mut file, &existing_snaps, relative_path, config.format);
 
         // We don't care if unlock fails because the OS will automatically unlock the file
         //  when it closes or the process terminates.  We will be closing the file handle
         //  on drop.
-        match file.unlock() {
-            _ => (),
+        let _ = file.unlock();
+    }
+
+    /// Drops this snapshot's entry from a file if it has one, deleting the file once it's empty.
+    fn remove_from(&self, spec: &SnapFileSpec, config: &Config) {
+        let mut file = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&spec.absolute_path)
+        {
+            Ok(f) => f,
+            Err(_) => return,
         };
+
+        file.lock_exclusive().expect(OS_LOCK_FILE_FAIL);
+
+        let mut existing_snaps = parse_snaps_from_file(&file, &spec.relative_path);
+        if existing_snaps.remove(&self.module_key()).is_some() {
+            if existing_snaps.is_empty() {
+                let _ = remove_file(&spec.absolute_path);
+            } else {
+                write_snaps_to_file(
+                    &mut file,
+                    &existing_snaps,
+                    &spec.relative_path,
+                    config.format,
+                );
+            }
+        }
+
+        let _ = file.unlock();
     }
 
     fn module_key(&self) -> String {
@@ -172,37 +426,42 @@ where
         snapshot_key
     }
 
-    fn create_deserializable(&self) -> Snapshot<serde_json::Value> {
+    fn normalize(&self, value: &mut serde_json::Value, config: &Config) {
+        let redactions = match config.redactions() {
+            Ok(redactions) => redactions,
+            Err(why) => panic!("Unable to use snapshot redactions: {}", why),
+        };
+
+        normalize::redact(value, &redactions);
+        normalize::sort_arrays(value, &self.unordered);
+    }
+
+    fn create_deserializable(&self, config: &Config) -> Snapshot<serde_json::Value> {
         match serde_json::to_value(&self.recorded_value) {
-            Ok(v) => Snapshot {
-                file: self.file.clone(),
-                test_function: self.test_function.clone(),
-                module_path: self.module_path.clone(),
-                recorded_value: v,
-            },
+            Ok(mut v) => {
+                self.normalize(&mut v, config);
+
+                Snapshot {
+                    file: self.file.clone(),
+                    test_function: self.test_function.clone(),
+                    module_path: self.module_path.clone(),
+                    recorded_value: v,
+                    unordered: self.unordered.clone(),
+                }
+            }
             Err(why) => panic!("Unable to serialize test value: {:?}", why),
         }
     }
 
-    fn path(&self, manifest_dir: &str) -> SnapFileSpec {
-        let mut components = self.file.iter();
-
-        // strip the filename
-        let mut filename = components.next_back().unwrap().clone();
-        filename.push_str(".snap");
-
-        let mut dir = PathBuf::new();
-        for directory in components {
-            dir.push(directory);
-        }
-
-        dir.push("__snapshots__");
+    fn path(&self, manifest_dir: &str, layout: &Layout) -> SnapFileSpec {
+        let dir = layout.snap_dir(&self.file);
+        let filename = layout.snap_file_name(&self.file);
 
         let mut absolute_path = PathBuf::from(manifest_dir);
         absolute_path.push(dir.clone());
         absolute_path.push(filename.clone());
 
-        let mut relative_path = PathBuf::from(dir.clone());
+        let mut relative_path = dir.clone();
         relative_path.push(filename.clone());
 
         SnapFileSpec {
@@ -213,8 +472,62 @@ where
     }
 }
 
+/// Why a snapshot assertion failed.
+enum Failure {
+    Missing(String),
+    Mismatched(String),
+}
+
+fn load_config(manifest_dir: &str) -> Config {
+    match Config::load(Path::new(manifest_dir)) {
+        Ok(config) => config,
+        Err(why) => panic!("Unable to load snapshot config: {}", why),
+    }
+}
+
+fn expect_eq<T: Debug + PartialEq>(left: &T, right: &T, message: &str) -> Result<(), String> {
+    if left == right {
+        Ok(())
+    } else {
+        Err(format!(
+            "assertion failed: `(left == right)`: {}\n\n{}\n",
+            message,
+            Comparison::new(left, right)
+        ))
+    }
+}
+
+fn describe_mismatch<T: Debug + PartialEq>(
+    config: &Config,
+    actual: &T,
+    recorded: &T,
+    expected_value: &serde_json::Value,
+    actual_value: &serde_json::Value,
+) -> String {
+    if config.diff == DiffStyle::Structural {
+        let changes = diff::diff_values(expected_value, actual_value);
+
+        // values can differ by PartialEq without differing once serialized
+        if !changes.is_empty() {
+            return format!(
+                "Test output doesn't match recorded snapshot!\n{}",
+                diff::render(&changes)
+            );
+        }
+    }
+
+    match expect_eq(
+        actual,
+        recorded,
+        "Test output doesn't match recorded snapshot!",
+    ) {
+        Err(why) => why,
+        Ok(()) => "Test output doesn't match recorded snapshot!".to_owned(),
+    }
+}
+
 fn truncate_file(file: &mut File) {
-    let file_len = file.seek(SeekFrom::Current(0)).unwrap();
+    let file_len = file.stream_position().unwrap();
     file.set_len(file_len).unwrap();
 }
 
@@ -226,7 +539,7 @@ fn parse_snaps_from_file(file: &File, relative_path: &Path) -> SnapFileContents
     match serde_json::from_str(&contents) {
         Ok(v) => v,
         Err(why) => {
-            if contents.len() == 0 {
+            if contents.is_empty() {
                 eprintln!(
                     "Snapshot file does not appear to exist: {:?}",
                     relative_path
@@ -244,16 +557,25 @@ fn parse_snaps_from_file(file: &File, relative_path: &Path) -> SnapFileContents
     }
 }
 
-fn write_snaps_to_file(file: &mut File, snapshots: &SnapFileContents, relative_path: &Path) {
+fn write_snaps_to_file(
+    file: &mut File,
+    snapshots: &SnapFileContents,
+    relative_path: &Path,
+    format: Format,
+) {
     file.seek(SeekFrom::Start(0)).unwrap();
 
     let writer = BufWriter::new(file.duplicate().expect(OS_CLONE_FILE_FAIL));
-    match serde_json::to_writer_pretty(writer, &snapshots) {
-        Err(why) => panic!(
+    let written = match format {
+        Format::Pretty => serde_json::to_writer_pretty(writer, &snapshots),
+        Format::Compact => serde_json::to_writer(writer, &snapshots),
+    };
+
+    if let Err(why) = written {
+        panic!(
             "Unable to serialize or write snapshot result to {:?}: {:?}",
             relative_path, why
-        ),
-        _ => {}
+        );
     }
 
     truncate_file(file);
@@ -264,3 +586,14 @@ struct SnapFileSpec {
     relative_path: PathBuf,
     absolute_path: PathBuf,
 }
+
+impl SnapFileSpec {
+    /// The side file holding snapshots waiting for review.
+    fn pending(&self) -> SnapFileSpec {
+        SnapFileSpec {
+            dir: self.dir.clone(),
+            relative_path: Layout::pending_path(&self.relative_path),
+            absolute_path: Layout::pending_path(&self.absolute_path),
+        }
+    }
+}
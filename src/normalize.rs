@@ -0,0 +1,124 @@
+use serde_json::Value;
+use std::cmp::Ordering;
+
+use crate::selector::{Segment, Selector};
+
+/// Which arrays in a recorded value are compared as unordered multisets.
+///
+/// Matching arrays are sorted into a canonical order before they're compared or written, so
+/// output derived from `HashMap`/`HashSet` iteration doesn't flip between runs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Unordered {
+    #[default]
+    Nowhere,
+    Everywhere,
+    At(Vec<Selector>),
+}
+
+impl Unordered {
+    fn applies_to(&self, path: &[Segment]) -> bool {
+        match self {
+            Unordered::Nowhere => false,
+            Unordered::Everywhere => true,
+            Unordered::At(selectors) => selectors.iter().any(|s| s.matches(path)),
+        }
+    }
+}
+
+pub(crate) fn sort_arrays(value: &mut Value, unordered: &Unordered) {
+    if *unordered != Unordered::Nowhere {
+        sort_arrays_at(value, unordered, &mut Vec::new());
+    }
+}
+
+fn sort_arrays_at(value: &mut Value, unordered: &Unordered, path: &mut Vec<Segment>) {
+    match value {
+        Value::Array(items) => {
+            // sort nested arrays first so that the canonical order of this one is stable too
+            for (idx, item) in items.iter_mut().enumerate() {
+                path.push(Segment::Index(idx));
+                sort_arrays_at(item, unordered, path);
+                path.pop();
+            }
+
+            if unordered.applies_to(path) {
+                items.sort_by(canonical_order);
+            }
+        }
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                path.push(Segment::Key(key.clone()));
+                sort_arrays_at(field, unordered, path);
+                path.pop();
+            }
+        }
+        _ => (),
+    }
+}
+
+fn canonical_order(a: &Value, b: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match v {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5,
+        }
+    }
+
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (a.as_f64(), b.as_f64());
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| canonical_order(a, b))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Value::Object(a), Value::Object(b)) => a
+            .iter()
+            .zip(b)
+            .map(|((ak, av), (bk, bv))| ak.cmp(bk).then_with(|| canonical_order(av, bv)))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// Replaces everything matched by a redaction's selector with its replacement value.
+pub(crate) fn redact(value: &mut Value, redactions: &[(Selector, Value)]) {
+    if !redactions.is_empty() {
+        redact_at(value, redactions, &mut Vec::new());
+    }
+}
+
+fn redact_at(value: &mut Value, redactions: &[(Selector, Value)], path: &mut Vec<Segment>) {
+    if let Some((_, replacement)) = redactions.iter().find(|(s, _)| s.matches(path)) {
+        *value = replacement.clone();
+        return;
+    }
+
+    match value {
+        Value::Array(items) => {
+            for (idx, item) in items.iter_mut().enumerate() {
+                path.push(Segment::Index(idx));
+                redact_at(item, redactions, path);
+                path.pop();
+            }
+        }
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                path.push(Segment::Key(key.clone()));
+                redact_at(field, redactions, path);
+                path.pop();
+            }
+        }
+        _ => (),
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// One step into a recorded value: either an object key or an array index.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Matcher {
+    Key(String),
+    AnyKey,
+    Index(usize),
+    AnyIndex,
+}
+
+/// Addresses parts of a recorded value, like `.items[*].tags` or `.by_id.*`.
+///
+/// `.name` selects an object key, `[3]` an array index, and `.*`/`[*]` match any key/index.
+/// A lone `.` selects the root value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Selector {
+    source: String,
+    matchers: Vec<Matcher>,
+}
+
+impl Selector {
+    pub fn matches(&self, path: &[Segment]) -> bool {
+        self.matchers.len() == path.len()
+            && self
+                .matchers
+                .iter()
+                .zip(path)
+                .all(|(matcher, segment)| match (matcher, segment) {
+                    (Matcher::AnyKey, Segment::Key(_)) => true,
+                    (Matcher::AnyIndex, Segment::Index(_)) => true,
+                    (Matcher::Key(expected), Segment::Key(actual)) => expected == actual,
+                    (Matcher::Index(expected), Segment::Index(actual)) => expected == actual,
+                    _ => false,
+                })
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let invalid = |why: &str| format!("invalid selector {:?}: {}", source, why);
+
+        let mut matchers = Vec::new();
+        let mut rest = source.trim();
+
+        if rest == "." {
+            rest = "";
+        } else if rest.is_empty() {
+            return Err(invalid("empty selector, use `.` for the root value"));
+        }
+
+        while !rest.is_empty() {
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+                let key = &after_dot[..end];
+
+                matchers.push(match key {
+                    "" => return Err(invalid("expected a key after `.`")),
+                    "*" => Matcher::AnyKey,
+                    key => Matcher::Key(key.to_owned()),
+                });
+                rest = &after_dot[end..];
+            } else if let Some(after_bracket) = rest.strip_prefix('[') {
+                let end = match after_bracket.find(']') {
+                    Some(end) => end,
+                    None => return Err(invalid("unclosed `[`")),
+                };
+                let inner = &after_bracket[..end];
+
+                matchers.push(if inner == "*" {
+                    Matcher::AnyIndex
+                } else if inner.starts_with('"') {
+                    match serde_json::from_str(inner) {
+                        Ok(key) => Matcher::Key(key),
+                        Err(_) => return Err(invalid("malformed quoted key")),
+                    }
+                } else {
+                    match inner.parse() {
+                        Ok(idx) => Matcher::Index(idx),
+                        Err(_) => return Err(invalid("expected an index, `*` or a quoted key")),
+                    }
+                });
+                rest = &after_bracket[end + 1..];
+            } else {
+                return Err(invalid("expected `.` or `[`"));
+            }
+        }
+
+        Ok(Selector {
+            source: source.trim().to_owned(),
+            matchers,
+        })
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Segment::Index(idx) => write!(f, "[{}]", idx),
+            Segment::Key(key) => {
+                let plain = !key.is_empty()
+                    && key
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+
+                if plain {
+                    write!(f, ".{}", key)
+                } else {
+                    write!(f, "[{}]", serde_json::Value::from(key.as_str()))
+                }
+            }
+        }
+    }
+}
+
+/// Renders a concrete path in selector syntax, e.g. `.items[3].name`.
+pub fn display_path(path: &[Segment]) -> String {
+    if path.is_empty() {
+        return ".".to_owned();
+    }
+
+    path.iter().map(|segment| segment.to_string()).collect()
+}
@@ -0,0 +1,112 @@
+use serde_derive::{Deserialize, Serialize};
+use snapshot::config::{Config, SNAPSHOT_CI_VAR};
+use snapshot::Snapshot;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Event {
+    name: String,
+    created_at: u64,
+}
+
+fn project_with_config(name: &str, config: &str) -> PathBuf {
+    // these tests write snapshots, which is never allowed in CI
+    env::set_var(SNAPSHOT_CI_VAR, "0");
+
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("snapshot.toml"), config).unwrap();
+    dir
+}
+
+fn event_snapshot(created_at: u64) -> Snapshot<Event> {
+    Snapshot::new(
+        "tests/config.rs".to_owned(),
+        "config".to_owned(),
+        "event_snapshot".to_owned(),
+        Event {
+            name: "launch".to_owned(),
+            created_at,
+        },
+    )
+}
+
+#[test]
+fn redacted_values_are_ignored() {
+    let dir = project_with_config(
+        "redactions",
+        r#"
+            format = "compact"
+            dir = "snapshots"
+
+            [redactions]
+            ".created_at" = "[timestamp]"
+        "#,
+    );
+    let manifest_dir = dir.to_str().unwrap();
+
+    event_snapshot(1).update_snapshot(manifest_dir);
+    event_snapshot(2).check_snapshot(manifest_dir);
+
+    let written = fs::read_to_string(dir.join("snapshots/tests/config.rs.snap")).unwrap();
+    assert!(!written.contains('\n'));
+    assert!(written.contains(r#""created_at":"[timestamp]""#));
+}
+
+#[test]
+#[should_panic(expected = "writes are disabled by `strict`")]
+fn strict_mode_refuses_writes() {
+    let dir = project_with_config("strict", "strict = true");
+
+    event_snapshot(1).update_snapshot(dir.to_str().unwrap());
+}
+
+#[test]
+#[should_panic(expected = "is not recorded, run `cargo snapshot update` locally")]
+fn strict_mode_explains_missing_snapshots() {
+    let dir = project_with_config("strict-missing", "strict = true");
+
+    event_snapshot(1).check_snapshot(dir.to_str().unwrap());
+}
+
+#[test]
+fn ci_is_strict_whatever_the_config_says() {
+    let config = Config {
+        strict: Some(false),
+        ci: true,
+        ..Config::default()
+    };
+
+    assert!(config.is_strict());
+}
+
+#[test]
+fn new_mode_records_missing_snapshots_only() {
+    let dir = project_with_config("update-new", "update = \"new\"");
+    let manifest_dir = dir.to_str().unwrap();
+
+    event_snapshot(1).assert_snapshot(manifest_dir);
+    event_snapshot(1).check_snapshot(manifest_dir);
+
+    let mismatch = std::panic::catch_unwind(|| event_snapshot(2).assert_snapshot(manifest_dir));
+    assert!(mismatch.is_err());
+    event_snapshot(1).check_snapshot(manifest_dir);
+}
+
+#[test]
+fn pending_mode_writes_side_files() {
+    let dir = project_with_config("update-pending", "update = \"pending\"");
+    let manifest_dir = dir.to_str().unwrap();
+    let pending_file = dir.join("tests/__snapshots__/config.rs.snap.pending");
+
+    let missing = std::panic::catch_unwind(|| event_snapshot(1).assert_snapshot(manifest_dir));
+    assert!(missing.is_err());
+    assert!(pending_file.exists());
+    assert!(!dir.join("tests/__snapshots__/config.rs.snap").exists());
+
+    event_snapshot(1).update_snapshot(manifest_dir);
+    assert!(!pending_file.exists());
+}
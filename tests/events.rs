@@ -0,0 +1,66 @@
+use snapshot::config::SNAPSHOT_CI_VAR;
+use snapshot::events::{self, Outcome, SNAPSHOT_EVENT_LOG_VAR};
+use snapshot::Snapshot;
+use std::env;
+use std::fs;
+use std::panic::catch_unwind;
+use std::path::PathBuf;
+
+fn count_snapshot(count: u32) -> Snapshot<u32> {
+    Snapshot::new(
+        "tests/events.rs".to_owned(),
+        "events".to_owned(),
+        "count_snapshot".to_owned(),
+        count,
+    )
+}
+
+#[test]
+fn assertions_are_logged() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("events");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let log = dir.join("events.jsonl");
+    env::set_var(SNAPSHOT_EVENT_LOG_VAR, &log);
+    // writing snapshots is never allowed in CI
+    env::set_var(SNAPSHOT_CI_VAR, "0");
+    let manifest_dir = dir.to_str().unwrap();
+
+    assert!(catch_unwind(|| count_snapshot(1).check_snapshot(manifest_dir)).is_err());
+    count_snapshot(1).update_snapshot(manifest_dir);
+    count_snapshot(1).check_snapshot(manifest_dir);
+    assert!(catch_unwind(|| count_snapshot(2).check_snapshot(manifest_dir)).is_err());
+
+    let logged = events::read_log(&log).unwrap();
+    let outcomes: Vec<_> = logged.iter().map(|event| event.outcome).collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            Outcome::Missing,
+            Outcome::Written,
+            Outcome::Matched,
+            Outcome::Mismatched
+        ]
+    );
+
+    assert!(logged.iter().all(|event| event.key == "events::count_snapshot"));
+    assert_eq!(logged[1].file, PathBuf::from("tests/__snapshots__/events.rs.snap"));
+    assert_eq!(logged[1].source_file, PathBuf::from("tests/events.rs"));
+    assert_eq!(logged[2].old_hash, logged[2].new_hash);
+    assert_eq!(logged[3].old_hash, logged[2].new_hash);
+    assert_ne!(logged[3].old_hash, logged[3].new_hash);
+    assert_eq!(logged[2].new_value, None);
+    assert_eq!(logged[3].new_value, Some(2.into()));
+    assert!(logged[3]
+        .message
+        .as_ref()
+        .unwrap()
+        .contains("doesn't match recorded snapshot"));
+}
+
+#[test]
+fn hashes_are_stable() {
+    let value: serde_json::Value = serde_json::from_str(r#"{"a": [1, 2]}"#).unwrap();
+    assert_eq!(events::hash_value(&value), "4ab48197316c446f");
+}
@@ -0,0 +1,26 @@
+mod test {
+    use serde_derive::{Deserialize, Serialize};
+    use snapshot::snapshot;
+    use std::collections::{HashMap, HashSet};
+
+    #[snapshot(unordered)]
+    fn hash_set_snapshot() -> HashSet<String> {
+        (0..20).map(|i| format!("item {}", i)).collect()
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Inventory {
+        owners: Vec<String>,
+        counts: Vec<(String, u32)>,
+    }
+
+    #[snapshot(unordered = ".counts")]
+    fn selected_paths_snapshot() -> Inventory {
+        let counts: HashMap<String, u32> = (0..20).map(|i| (format!("part {}", i), i)).collect();
+
+        Inventory {
+            owners: vec!["zed".to_owned(), "amy".to_owned()],
+            counts: counts.into_iter().collect(),
+        }
+    }
+}
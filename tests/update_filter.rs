@@ -0,0 +1,59 @@
+use snapshot::config::{SNAPSHOT_CI_VAR, UPDATE_SNAPSHOTS_FILTER_VAR, UPDATE_SNAPSHOTS_VAR};
+use snapshot::Snapshot;
+use std::env;
+use std::fs;
+use std::panic::catch_unwind;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn count_snapshot(test_function: &str, count: u32) -> Snapshot<u32> {
+    Snapshot::new(
+        "tests/update_filter.rs".to_owned(),
+        "update_filter".to_owned(),
+        test_function.to_owned(),
+        count,
+    )
+}
+
+fn project_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("update-filter")
+}
+
+/// Asserts both snapshots with new values. Only does anything when run by the test below, which
+/// sets the update variables for this process alone so they can't leak into other tests.
+#[test]
+#[ignore]
+fn assert_with_update_filter() {
+    if env::var_os(UPDATE_SNAPSHOTS_FILTER_VAR).is_none() {
+        return;
+    }
+    let manifest_dir = project_dir();
+    let manifest_dir = manifest_dir.to_str().unwrap();
+
+    count_snapshot("first", 2).assert_snapshot(manifest_dir);
+    assert!(catch_unwind(|| count_snapshot("second", 2).assert_snapshot(manifest_dir)).is_err());
+}
+
+#[test]
+fn only_filtered_snapshots_are_updated() {
+    let dir = project_dir();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    // writing snapshots is never allowed in CI
+    env::set_var(SNAPSHOT_CI_VAR, "0");
+    let manifest_dir = dir.to_str().unwrap();
+
+    count_snapshot("first", 1).update_snapshot(manifest_dir);
+    count_snapshot("second", 1).update_snapshot(manifest_dir);
+
+    let child = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "assert_with_update_filter", "--ignored"])
+        .env(UPDATE_SNAPSHOTS_VAR, "always")
+        .env(UPDATE_SNAPSHOTS_FILTER_VAR, "update_filter::first, update_filter::other")
+        .output()
+        .unwrap();
+    assert!(child.status.success(), "{}", String::from_utf8_lossy(&child.stdout));
+
+    count_snapshot("first", 2).check_snapshot(manifest_dir);
+    count_snapshot("second", 1).check_snapshot(manifest_dir);
+}